@@ -0,0 +1,1576 @@
+//! Minimal RFC 8555 ACME server issuing certificates from the DevRelay CA, so tools such as
+//! Caddy, Traefik, cert-manager or certbot can obtain certificates without extra setup.
+//!
+//! Accounts are kept on disk (so clients survive a restart); orders, authorizations and
+//! issued certificates live in memory until the order expires. Nonces are capped, with the
+//! oldest forgotten first (clients retry on badNonce).
+
+use crate::certs::CertManager;
+use crate::config::{AcmeConfig, Config};
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use bytes::Bytes;
+use openssl::bn::BigNum;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey, Public};
+use openssl::rsa::Rsa;
+use openssl::sign::Verifier;
+use pingora::http::ResponseHeader;
+use pingora_proxy::Session;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Largest request body accepted (JWS-wrapped CSRs are a few KB)
+const MAX_BODY_SIZE: usize = 64 * 1024;
+/// Largest http-01 challenge response read from a client
+const MAX_CHALLENGE_RESPONSE_SIZE: usize = 16 * 1024;
+const CHALLENGE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Lifetime of orders, their authorizations and certificates
+const ORDER_LIFETIME_DAYS: i64 = 7;
+/// Outstanding nonces remembered; older ones are dropped
+const MAX_NONCES: usize = 10_000;
+/// Unexpired orders kept at once
+const MAX_ORDERS: usize = 1_000;
+
+const ERROR_PREFIX: &str = "urn:ietf:params:acme:error:";
+
+pub struct AcmeServer {
+    config: Arc<Config>,
+    cert_manager: Arc<CertManager>,
+    host: String,
+    auto_approve: bool,
+    cert_days: i64,
+    accounts_dir: PathBuf,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    nonces: HashSet<String>,
+    /// Nonces in issue order, for dropping the oldest; may hold ones already used
+    nonce_order: VecDeque<String>,
+    accounts: HashMap<String, Account>,
+    orders: HashMap<String, Order>,
+    authorizations: HashMap<String, Authorization>,
+    challenges: HashMap<String, String>,
+    certificates: HashMap<String, String>,
+}
+
+#[derive(Clone)]
+struct Account {
+    key: PKey<Public>,
+    thumbprint: String,
+    contact: Vec<String>,
+}
+
+#[derive(Clone)]
+struct Order {
+    account: String,
+    status: &'static str,
+    expires: OffsetDateTime,
+    identifiers: Vec<String>,
+    authorizations: Vec<String>,
+    certificate: Option<String>,
+}
+
+#[derive(Clone)]
+struct Authorization {
+    identifier: String,
+    wildcard: bool,
+    status: &'static str,
+    expires: OffsetDateTime,
+    challenge: String,
+    token: String,
+    account_thumbprint: String,
+    error: Option<String>,
+}
+
+struct AcmeResponse {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl AcmeResponse {
+    fn json(status: u16, body: Value) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type", "application/json".to_string())],
+            body: body.to_string().into_bytes(),
+        }
+    }
+
+    fn problem(status: u16, kind: &str, detail: impl Into<String>) -> Self {
+        let body = json!({ "type": format!("{}{}", ERROR_PREFIX, kind), "detail": detail.into() });
+        Self {
+            status,
+            headers: vec![("Content-Type", "application/problem+json".to_string())],
+            body: body.to_string().into_bytes(),
+        }
+    }
+
+    fn empty(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    fn with_location(mut self, location: String) -> Self {
+        self.headers.push(("Location", location));
+        self
+    }
+}
+
+type AcmeResult<T> = std::result::Result<T, AcmeResponse>;
+
+/// JWS in flattened JSON serialization (RFC 8555 section 6.2)
+#[derive(Deserialize)]
+struct Jws {
+    protected: String,
+    payload: String,
+    signature: String,
+}
+
+#[derive(Deserialize)]
+struct ProtectedHeader {
+    alg: String,
+    nonce: Option<String>,
+    url: String,
+    jwk: Option<Value>,
+    kid: Option<String>,
+}
+
+/// A verified request: the signing account (or new key) and the decoded payload.
+struct SignedRequest {
+    jwk: Option<Value>,
+    key: PKey<Public>,
+    account: Option<String>,
+    payload: Option<Value>,
+}
+
+impl AcmeServer {
+    pub fn new(
+        config: Arc<Config>,
+        acme: &AcmeConfig,
+        cert_manager: Arc<CertManager>,
+    ) -> Result<Self> {
+        cert_manager.unlock_ca_key()?;
+
+        let accounts_dir = PathBuf::from(&config.tls.cert_dir).join("acme-accounts");
+        crate::certs::create_private_dir(&accounts_dir)?;
+
+        Ok(Self {
+            host: acme.host.to_ascii_lowercase(),
+            auto_approve: acme.auto_approve,
+            cert_days: i64::from(acme.cert_days),
+            accounts_dir,
+            cert_manager,
+            config,
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    /// Whether requests for `host` (a Host header, possibly with a port) go to the ACME server.
+    pub fn serves(&self, host: &str) -> bool {
+        host.split(':')
+            .next()
+            .unwrap_or(host)
+            .eq_ignore_ascii_case(&self.host)
+    }
+
+    /// Answer an ACME request on the proxy session.
+    pub async fn serve(&self, session: &mut Session, host: &str) -> pingora_core::Result<()> {
+        let method = session.req_header().method.as_str().to_string();
+        let path = session.req_header().uri.path().to_string();
+        let base_url = format!("https://{}", host);
+
+        let mut body = Vec::new();
+        while let Some(chunk) = session.read_request_body().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() > MAX_BODY_SIZE {
+                break;
+            }
+        }
+
+        let response = if body.len() > MAX_BODY_SIZE {
+            AcmeResponse::problem(413, "malformed", "Request body too large")
+        } else {
+            self.handle(&method, &path, &base_url, &body).await
+        };
+
+        let mut header = ResponseHeader::build(response.status, None)?;
+        for (name, value) in &response.headers {
+            header.insert_header(*name, value.as_str())?;
+        }
+        header.insert_header("Replay-Nonce", self.new_nonce())?;
+        header.insert_header("Cache-Control", "no-store")?;
+        header.insert_header("Link", format!("<{}/directory>;rel=\"index\"", base_url))?;
+        header.insert_header("Content-Length", response.body.len().to_string())?;
+
+        session
+            .write_response_header(Box::new(header), false)
+            .await?;
+        session
+            .write_response_body(Some(Bytes::from(response.body)), true)
+            .await
+    }
+
+    async fn handle(&self, method: &str, path: &str, base_url: &str, body: &[u8]) -> AcmeResponse {
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        let result = match (method, segments.as_slice()) {
+            ("GET", ["directory"]) | ("GET", [""]) => Ok(self.directory(base_url)),
+            ("HEAD" | "GET", ["new-nonce"]) => Ok(AcmeResponse::empty(if method == "HEAD" {
+                200
+            } else {
+                204
+            })),
+            ("POST", _) => self.handle_post(&segments, path, base_url, body).await,
+            _ => Err(AcmeResponse::problem(
+                404,
+                "malformed",
+                format!("Not found: {} {}", method, path),
+            )),
+        };
+        result.unwrap_or_else(|problem| problem)
+    }
+
+    async fn handle_post(
+        &self,
+        segments: &[&str],
+        path: &str,
+        base_url: &str,
+        body: &[u8],
+    ) -> AcmeResult<AcmeResponse> {
+        let request = self.verify_jws(body, path)?;
+
+        match segments {
+            ["new-account"] => self.new_account(request, base_url),
+            ["key-change"] => Err(AcmeResponse::problem(
+                501,
+                "malformed",
+                "Account key rollover is not supported; create a new account instead",
+            )),
+            [_, ..] if request.account.is_none() => Err(AcmeResponse::problem(
+                400,
+                "malformed",
+                "Requests must be signed with an account key (kid)",
+            )),
+            ["acct", id] => self.get_account(&request, id, base_url),
+            ["acct", id, "orders"] => self.list_orders(&request, id, base_url),
+            ["new-order"] => self.new_order(&request, base_url),
+            ["order", id] => self.get_order(&request, id, base_url),
+            ["order", id, "finalize"] => self.finalize(&request, id, base_url),
+            ["authz", id] => self.get_authorization(&request, id, base_url),
+            ["chall", id] => self.respond_to_challenge(&request, id, base_url).await,
+            ["cert", id] => self.get_certificate(&request, id),
+            ["revoke-cert"] => Ok(AcmeResponse::empty(200)),
+            _ => Err(AcmeResponse::problem(
+                404,
+                "malformed",
+                format!("Not found: POST {}", path),
+            )),
+        }
+    }
+
+    fn directory(&self, base_url: &str) -> AcmeResponse {
+        AcmeResponse::json(
+            200,
+            json!({
+                "newNonce": format!("{}/new-nonce", base_url),
+                "newAccount": format!("{}/new-account", base_url),
+                "newOrder": format!("{}/new-order", base_url),
+                "revokeCert": format!("{}/revoke-cert", base_url),
+                "keyChange": format!("{}/key-change", base_url),
+                "meta": { "externalAccountRequired": false },
+            }),
+        )
+    }
+
+    fn new_nonce(&self) -> String {
+        let nonce = random_id();
+        let mut state = self.state.lock().unwrap();
+        state.nonces.insert(nonce.clone());
+        state.nonce_order.push_back(nonce.clone());
+        while state.nonce_order.len() > MAX_NONCES {
+            if let Some(oldest) = state.nonce_order.pop_front() {
+                state.nonces.remove(&oldest);
+            }
+        }
+        nonce
+    }
+
+    /// Check the JWS signature, nonce and URL of a POST and decode its payload.
+    fn verify_jws(&self, body: &[u8], path: &str) -> AcmeResult<SignedRequest> {
+        let jws: Jws = serde_json::from_slice(body)
+            .map_err(|e| AcmeResponse::problem(400, "malformed", format!("Invalid JWS: {}", e)))?;
+        let protected: ProtectedHeader = decode_json(&jws.protected).map_err(|e| {
+            AcmeResponse::problem(400, "malformed", format!("Invalid JWS header: {}", e))
+        })?;
+
+        let nonce = protected.nonce.as_deref().unwrap_or_default();
+        if !self.state.lock().unwrap().nonces.remove(nonce) {
+            return Err(AcmeResponse::problem(
+                400,
+                "badNonce",
+                "Unknown or reused nonce",
+            ));
+        }
+        // Compare paths only: containers may reach the directory under a different host name
+        if url_path(&protected.url) != path {
+            return Err(AcmeResponse::problem(
+                401,
+                "unauthorized",
+                format!("JWS url {} does not match the request", protected.url),
+            ));
+        }
+
+        let (key, account) = match (&protected.jwk, &protected.kid) {
+            (Some(jwk), None) => (
+                jwk_public_key(jwk)
+                    .map_err(|e| AcmeResponse::problem(400, "badPublicKey", e.to_string()))?,
+                None,
+            ),
+            (None, Some(kid)) => {
+                let id = url_path(kid)
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or_default()
+                    .to_string();
+                let account = self.account(&id).ok_or_else(|| {
+                    AcmeResponse::problem(
+                        400,
+                        "accountDoesNotExist",
+                        format!("Unknown account {}", kid),
+                    )
+                })?;
+                (account.key, Some(id))
+            }
+            _ => {
+                return Err(AcmeResponse::problem(
+                    400,
+                    "malformed",
+                    "JWS header must contain exactly one of jwk and kid",
+                ));
+            }
+        };
+
+        let signing_input = format!("{}.{}", jws.protected, jws.payload);
+        let signature = URL_SAFE_NO_PAD.decode(&jws.signature).map_err(|_| {
+            AcmeResponse::problem(400, "malformed", "Invalid JWS signature encoding")
+        })?;
+        let valid = verify_signature(&protected.alg, &key, signing_input.as_bytes(), &signature)
+            .map_err(|e| AcmeResponse::problem(400, "badSignatureAlgorithm", e.to_string()))?;
+        if !valid {
+            return Err(AcmeResponse::problem(
+                401,
+                "unauthorized",
+                "JWS signature is invalid",
+            ));
+        }
+
+        let payload = if jws.payload.is_empty() {
+            None // POST-as-GET
+        } else {
+            Some(decode_json(&jws.payload).map_err(|e| {
+                AcmeResponse::problem(400, "malformed", format!("Invalid JWS payload: {}", e))
+            })?)
+        };
+
+        Ok(SignedRequest {
+            jwk: protected.jwk,
+            key,
+            account,
+            payload,
+        })
+    }
+
+    /// Look up an account, loading it from disk if it was created before a restart.
+    fn account(&self, id: &str) -> Option<Account> {
+        if let Some(account) = self.state.lock().unwrap().accounts.get(id) {
+            return Some(account.clone());
+        }
+        if !is_safe_id(id) {
+            return None;
+        }
+        let stored: Value = serde_json::from_slice(&fs::read(self.account_path(id)).ok()?).ok()?;
+        let account = Account {
+            key: jwk_public_key(&stored["jwk"]).ok()?,
+            thumbprint: id.to_string(),
+            contact: contact_list(&stored),
+        };
+        self.state
+            .lock()
+            .unwrap()
+            .accounts
+            .insert(id.to_string(), account.clone());
+        Some(account)
+    }
+
+    fn account_path(&self, id: &str) -> PathBuf {
+        self.accounts_dir.join(format!("{}.json", id))
+    }
+
+    fn new_account(&self, request: SignedRequest, base_url: &str) -> AcmeResult<AcmeResponse> {
+        let jwk = request.jwk.ok_or_else(|| {
+            AcmeResponse::problem(400, "malformed", "newAccount must be signed with a jwk")
+        })?;
+        let payload = request.payload.unwrap_or_default();
+        let id = jwk_thumbprint(&jwk)
+            .map_err(|e| AcmeResponse::problem(400, "badPublicKey", e.to_string()))?;
+
+        if let Some(account) = self.account(&id) {
+            return Ok(account_response(200, &id, &account, base_url));
+        }
+        if payload["onlyReturnExisting"].as_bool() == Some(true) {
+            return Err(AcmeResponse::problem(
+                400,
+                "accountDoesNotExist",
+                "No account for this key",
+            ));
+        }
+
+        let account = Account {
+            key: request.key,
+            thumbprint: id.clone(),
+            contact: contact_list(&payload),
+        };
+        let stored = json!({ "jwk": jwk, "contact": account.contact });
+        fs::write(self.account_path(&id), stored.to_string()).map_err(|e| {
+            AcmeResponse::problem(
+                500,
+                "serverInternal",
+                format!("Failed to store account: {}", e),
+            )
+        })?;
+        self.state
+            .lock()
+            .unwrap()
+            .accounts
+            .insert(id.clone(), account.clone());
+
+        Ok(account_response(201, &id, &account, base_url))
+    }
+
+    fn get_account(
+        &self,
+        request: &SignedRequest,
+        id: &str,
+        base_url: &str,
+    ) -> AcmeResult<AcmeResponse> {
+        if request.account.as_deref() != Some(id) {
+            return Err(AcmeResponse::problem(
+                403,
+                "unauthorized",
+                "Not your account",
+            ));
+        }
+        let account = self
+            .account(id)
+            .ok_or_else(|| AcmeResponse::problem(400, "accountDoesNotExist", "Unknown account"))?;
+        Ok(account_response(200, id, &account, base_url))
+    }
+
+    fn list_orders(
+        &self,
+        request: &SignedRequest,
+        id: &str,
+        base_url: &str,
+    ) -> AcmeResult<AcmeResponse> {
+        if request.account.as_deref() != Some(id) {
+            return Err(AcmeResponse::problem(
+                403,
+                "unauthorized",
+                "Not your account",
+            ));
+        }
+        let state = self.state.lock().unwrap();
+        let orders: Vec<String> = state
+            .orders
+            .iter()
+            .filter(|(_, order)| order.account == id)
+            .map(|(order_id, _)| format!("{}/order/{}", base_url, order_id))
+            .collect();
+        Ok(AcmeResponse::json(200, json!({ "orders": orders })))
+    }
+
+    fn new_order(&self, request: &SignedRequest, base_url: &str) -> AcmeResult<AcmeResponse> {
+        let account_id = request.account.clone().unwrap_or_default();
+        let account = self
+            .account(&account_id)
+            .ok_or_else(|| AcmeResponse::problem(400, "accountDoesNotExist", "Unknown account"))?;
+        let payload = request.payload.clone().unwrap_or_default();
+
+        let mut identifiers = Vec::new();
+        for identifier in payload["identifiers"].as_array().into_iter().flatten() {
+            if identifier["type"] != "dns" {
+                return Err(AcmeResponse::problem(
+                    400,
+                    "unsupportedIdentifier",
+                    format!(
+                        "Only dns identifiers are supported, got {}",
+                        identifier["type"]
+                    ),
+                ));
+            }
+            let name = identifier["value"]
+                .as_str()
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+            if name.is_empty() {
+                return Err(AcmeResponse::problem(
+                    400,
+                    "malformed",
+                    "Empty dns identifier",
+                ));
+            }
+            if !self.cert_manager.ca_permits(&name).unwrap_or(false) {
+                return Err(AcmeResponse::problem(
+                    400,
+                    "rejectedIdentifier",
+                    format!("{} is outside the CA's name constraints", name),
+                ));
+            }
+            if !self.may_issue(&name) {
+                return Err(AcmeResponse::problem(
+                    400,
+                    "rejectedIdentifier",
+                    format!(
+                        "{} is neither a route host nor under the CA's name constraints",
+                        name
+                    ),
+                ));
+            }
+            if !identifiers.contains(&name) {
+                identifiers.push(name);
+            }
+        }
+        if identifiers.is_empty() {
+            return Err(AcmeResponse::problem(
+                400,
+                "malformed",
+                "Order has no identifiers",
+            ));
+        }
+
+        let expires = OffsetDateTime::now_utc() + time::Duration::days(ORDER_LIFETIME_DAYS);
+        let mut state = self.state.lock().unwrap();
+        remove_expired_orders(&mut state, OffsetDateTime::now_utc());
+        if state.orders.len() >= MAX_ORDERS {
+            return Err(AcmeResponse::problem(
+                429,
+                "rateLimited",
+                "Too many orders; try again when older ones expire",
+            ));
+        }
+        let mut authorizations = Vec::new();
+        for name in &identifiers {
+            let (identifier, wildcard) = match name.strip_prefix("*.") {
+                Some(base) => (base.to_string(), true),
+                None => (name.clone(), false),
+            };
+            let approved = self.auto_approve && self.is_known_host(name);
+            if wildcard && !approved {
+                return Err(AcmeResponse::problem(
+                    400,
+                    "rejectedIdentifier",
+                    format!(
+                        "{} needs auto_approve and a route host it covers (http-01 cannot validate wildcards)",
+                        name
+                    ),
+                ));
+            }
+
+            let authz_id = random_id();
+            let challenge_id = random_id();
+            state
+                .challenges
+                .insert(challenge_id.clone(), authz_id.clone());
+            state.authorizations.insert(
+                authz_id.clone(),
+                Authorization {
+                    identifier,
+                    wildcard,
+                    status: if approved { "valid" } else { "pending" },
+                    expires,
+                    challenge: challenge_id,
+                    token: random_id(),
+                    account_thumbprint: account.thumbprint.clone(),
+                    error: None,
+                },
+            );
+            authorizations.push(authz_id);
+        }
+
+        let order_id = random_id();
+        let mut order = Order {
+            account: account_id,
+            status: "pending",
+            expires,
+            identifiers,
+            authorizations,
+            certificate: None,
+        };
+        update_order_status(&mut order, &state);
+        let response = order_json(&order_id, &order, base_url);
+        state.orders.insert(order_id.clone(), order);
+
+        Ok(AcmeResponse::json(201, response)
+            .with_location(format!("{}/order/{}", base_url, order_id)))
+    }
+
+    /// Names certificates may be ordered for: route hosts (including names under wildcard
+    /// routes), and names under the CA's name constraints. Anything else would be validated over
+    /// the network, so whoever controls name resolution could get a certificate for any site.
+    fn may_issue(&self, name: &str) -> bool {
+        self.is_known_host(name)
+            || self.config.get_route_by_host(name).is_some()
+            || self
+                .cert_manager
+                .ca_constraints_permit(name)
+                .unwrap_or(false)
+    }
+
+    /// Route hosts count as known; a wildcard is known if it covers a route host.
+    fn is_known_host(&self, name: &str) -> bool {
+        self.config.routes.iter().any(|route| {
+            let host = route.host.to_ascii_lowercase();
+            match name.strip_prefix("*.") {
+                Some(base) => host
+                    .strip_suffix(base)
+                    .and_then(|label| label.strip_suffix('.'))
+                    .is_some_and(|label| !label.is_empty() && !label.contains('.')),
+                None => host == name,
+            }
+        })
+    }
+
+    fn owned_order(&self, request: &SignedRequest, id: &str) -> AcmeResult<Order> {
+        let mut state = self.state.lock().unwrap();
+        let mut order = state
+            .orders
+            .get(id)
+            .cloned()
+            .ok_or_else(|| AcmeResponse::problem(404, "malformed", "Unknown order"))?;
+        if request.account.as_deref() != Some(order.account.as_str()) {
+            return Err(AcmeResponse::problem(403, "unauthorized", "Not your order"));
+        }
+        update_order_status(&mut order, &state);
+        state.orders.insert(id.to_string(), order.clone());
+        Ok(order)
+    }
+
+    fn get_order(
+        &self,
+        request: &SignedRequest,
+        id: &str,
+        base_url: &str,
+    ) -> AcmeResult<AcmeResponse> {
+        let order = self.owned_order(request, id)?;
+        Ok(AcmeResponse::json(200, order_json(id, &order, base_url)))
+    }
+
+    fn finalize(
+        &self,
+        request: &SignedRequest,
+        id: &str,
+        base_url: &str,
+    ) -> AcmeResult<AcmeResponse> {
+        let mut order = self.owned_order(request, id)?;
+        if order.status != "ready" {
+            return Err(AcmeResponse::problem(
+                403,
+                "orderNotReady",
+                format!("Order is {}, not ready", order.status),
+            ));
+        }
+
+        let payload = request.payload.clone().unwrap_or_default();
+        let csr = payload["csr"]
+            .as_str()
+            .and_then(|csr| URL_SAFE_NO_PAD.decode(csr).ok())
+            .ok_or_else(|| AcmeResponse::problem(400, "badCSR", "Missing or invalid csr"))?;
+
+        let cert_pem = self
+            .cert_manager
+            .sign_csr(&csr, &order.identifiers, self.cert_days)
+            .map_err(|e| AcmeResponse::problem(400, "badCSR", format!("{:#}", e)))?;
+        let ca_pem = self
+            .cert_manager
+            .ca_cert_pem()
+            .map_err(|e| AcmeResponse::problem(500, "serverInternal", e.to_string()))?;
+
+        let cert_id = random_id();
+        order.status = "valid";
+        order.certificate = Some(cert_id.clone());
+
+        let mut state = self.state.lock().unwrap();
+        state.certificates.insert(cert_id, cert_pem + &ca_pem);
+        state.orders.insert(id.to_string(), order.clone());
+        println!(
+            "🔏 ACME: issued certificate for {}",
+            order.identifiers.join(", ")
+        );
+
+        Ok(AcmeResponse::json(200, order_json(id, &order, base_url))
+            .with_location(format!("{}/order/{}", base_url, id)))
+    }
+
+    fn owned_authorization(&self, request: &SignedRequest, id: &str) -> AcmeResult<Authorization> {
+        let account = self
+            .account(request.account.as_deref().unwrap_or_default())
+            .ok_or_else(|| AcmeResponse::problem(400, "accountDoesNotExist", "Unknown account"))?;
+        let authz = self
+            .state
+            .lock()
+            .unwrap()
+            .authorizations
+            .get(id)
+            .cloned()
+            .ok_or_else(|| AcmeResponse::problem(404, "malformed", "Unknown authorization"))?;
+        if authz.account_thumbprint != account.thumbprint {
+            return Err(AcmeResponse::problem(
+                403,
+                "unauthorized",
+                "Not your authorization",
+            ));
+        }
+        Ok(authz)
+    }
+
+    fn get_authorization(
+        &self,
+        request: &SignedRequest,
+        id: &str,
+        base_url: &str,
+    ) -> AcmeResult<AcmeResponse> {
+        let authz = self.owned_authorization(request, id)?;
+        Ok(AcmeResponse::json(
+            200,
+            authorization_json(&authz, base_url),
+        ))
+    }
+
+    async fn respond_to_challenge(
+        &self,
+        request: &SignedRequest,
+        challenge_id: &str,
+        base_url: &str,
+    ) -> AcmeResult<AcmeResponse> {
+        let authz_id = self
+            .state
+            .lock()
+            .unwrap()
+            .challenges
+            .get(challenge_id)
+            .cloned()
+            .ok_or_else(|| AcmeResponse::problem(404, "malformed", "Unknown challenge"))?;
+        let mut authz = self.owned_authorization(request, &authz_id)?;
+
+        // A POST-as-GET only reads the challenge; a payload (usually `{}`) asks us to validate it
+        if authz.status == "pending" && request.payload.is_some() {
+            let key_authorization = format!("{}.{}", authz.token, authz.account_thumbprint);
+            match self.fetch_http01(&authz.identifier, &authz.token).await {
+                Ok(body) if body.trim() == key_authorization => {
+                    authz.status = "valid";
+                    authz.error = None;
+                }
+                Ok(_) => {
+                    authz.status = "invalid";
+                    authz.error =
+                        Some("Challenge response does not match the key authorization".to_string());
+                }
+                Err(e) => {
+                    authz.status = "invalid";
+                    authz.error = Some(format!("{:#}", e));
+                }
+            }
+            if let Some(error) = &authz.error {
+                println!(
+                    "⚠️  ACME: http-01 validation for {} failed: {}",
+                    authz.identifier, error
+                );
+            }
+            self.state
+                .lock()
+                .unwrap()
+                .authorizations
+                .insert(authz_id.clone(), authz.clone());
+        }
+
+        Ok(AcmeResponse::json(200, challenge_json(&authz, base_url))
+            .with_location(format!("{}/chall/{}", base_url, challenge_id)))
+    }
+
+    /// Fetch http://<domain>/.well-known/acme-challenge/<token> through the route's backend,
+    /// or directly from the domain on port 80 when there is no route for it but the CA's name
+    /// constraints cover it.
+    async fn fetch_http01(&self, domain: &str, token: &str) -> Result<String> {
+        let (host, port) = match self.config.get_route_by_host(domain) {
+            Some(route) if route.backend_tls => anyhow::bail!(
+                "http-01 needs a plain HTTP backend for {} (or enable auto_approve)",
+                domain
+            ),
+            Some(route) => (route.backend.clone(), route.backend_port),
+            None if self.cert_manager.ca_constraints_permit(domain)? => (domain.to_string(), 80),
+            None => anyhow::bail!(
+                "{} is neither a route host nor under the CA's name constraints",
+                domain
+            ),
+        };
+
+        let request = format!(
+            "GET /.well-known/acme-challenge/{} HTTP/1.1\r\nHost: {}\r\nUser-Agent: devrelay-acme\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+            token, domain
+        );
+        let response = tokio::time::timeout(CHALLENGE_TIMEOUT, async {
+            let mut stream = tokio::net::TcpStream::connect((host.as_str(), port))
+                .await
+                .with_context(|| format!("Failed to connect to {}:{}", host, port))?;
+            stream.write_all(request.as_bytes()).await?;
+            let mut response = Vec::new();
+            stream
+                .take(MAX_CHALLENGE_RESPONSE_SIZE as u64)
+                .read_to_end(&mut response)
+                .await?;
+            anyhow::Ok(response)
+        })
+        .await
+        .with_context(|| format!("Timed out fetching the challenge from {}:{}", host, port))??;
+
+        parse_http_response(&response)
+    }
+
+    fn get_certificate(&self, request: &SignedRequest, id: &str) -> AcmeResult<AcmeResponse> {
+        let state = self.state.lock().unwrap();
+        let owned = state.orders.values().any(|order| {
+            order.certificate.as_deref() == Some(id)
+                && Some(order.account.as_str()) == request.account.as_deref()
+        });
+        let chain = state
+            .certificates
+            .get(id)
+            .filter(|_| owned)
+            .ok_or_else(|| AcmeResponse::problem(404, "malformed", "Unknown certificate"))?;
+
+        Ok(AcmeResponse {
+            status: 200,
+            headers: vec![(
+                "Content-Type",
+                "application/pem-certificate-chain".to_string(),
+            )],
+            body: chain.clone().into_bytes(),
+        })
+    }
+}
+
+/// Forget orders past their expiry, with their authorizations, challenges and certificates.
+fn remove_expired_orders(state: &mut State, now: OffsetDateTime) {
+    let expired: Vec<String> = state
+        .orders
+        .iter()
+        .filter(|(_, order)| order.expires <= now)
+        .map(|(id, _)| id.clone())
+        .collect();
+    for id in expired {
+        let Some(order) = state.orders.remove(&id) else {
+            continue;
+        };
+        for authz_id in &order.authorizations {
+            if let Some(authz) = state.authorizations.remove(authz_id) {
+                state.challenges.remove(&authz.challenge);
+            }
+        }
+        if let Some(cert) = &order.certificate {
+            state.certificates.remove(cert);
+        }
+    }
+}
+
+/// Orders become ready once every authorization is valid, and invalid if any failed.
+fn update_order_status(order: &mut Order, state: &State) {
+    if order.status != "pending" && order.status != "ready" {
+        return;
+    }
+    let statuses: Vec<&str> = order
+        .authorizations
+        .iter()
+        .map(|id| state.authorizations.get(id).map_or("invalid", |a| a.status))
+        .collect();
+    order.status = if statuses.contains(&"invalid") {
+        "invalid"
+    } else if statuses.iter().all(|s| *s == "valid") {
+        "ready"
+    } else {
+        "pending"
+    };
+}
+
+fn account_response(status: u16, id: &str, account: &Account, base_url: &str) -> AcmeResponse {
+    AcmeResponse::json(
+        status,
+        json!({
+            "status": "valid",
+            "contact": account.contact,
+            "orders": format!("{}/acct/{}/orders", base_url, id),
+        }),
+    )
+    .with_location(format!("{}/acct/{}", base_url, id))
+}
+
+fn order_json(id: &str, order: &Order, base_url: &str) -> Value {
+    let mut value = json!({
+        "status": order.status,
+        "expires": rfc3339(order.expires),
+        "identifiers": order
+            .identifiers
+            .iter()
+            .map(|name| json!({ "type": "dns", "value": name }))
+            .collect::<Vec<_>>(),
+        "authorizations": order
+            .authorizations
+            .iter()
+            .map(|authz| format!("{}/authz/{}", base_url, authz))
+            .collect::<Vec<_>>(),
+        "finalize": format!("{}/order/{}/finalize", base_url, id),
+    });
+    if let Some(cert) = &order.certificate {
+        value["certificate"] = json!(format!("{}/cert/{}", base_url, cert));
+    }
+    value
+}
+
+fn authorization_json(authz: &Authorization, base_url: &str) -> Value {
+    let mut value = json!({
+        "status": authz.status,
+        "expires": rfc3339(authz.expires),
+        "identifier": { "type": "dns", "value": authz.identifier },
+        "challenges": [challenge_json(authz, base_url)],
+    });
+    if authz.wildcard {
+        value["wildcard"] = json!(true);
+    }
+    value
+}
+
+fn challenge_json(authz: &Authorization, base_url: &str) -> Value {
+    let mut value = json!({
+        "type": "http-01",
+        "url": format!("{}/chall/{}", base_url, authz.challenge),
+        "token": authz.token,
+        "status": authz.status,
+    });
+    if let Some(error) = &authz.error {
+        value["error"] =
+            json!({ "type": format!("{}incorrectResponse", ERROR_PREFIX), "detail": error });
+    }
+    value
+}
+
+fn contact_list(value: &Value) -> Vec<String> {
+    value["contact"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|c| c.as_str().map(str::to_string))
+        .collect()
+}
+
+fn decode_json<T: serde::de::DeserializeOwned>(encoded: &str) -> Result<T> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(encoded)
+        .context("Invalid base64url")?;
+    serde_json::from_slice(&bytes).context("Invalid JSON")
+}
+
+fn decode_b64_field(jwk: &Value, field: &str) -> Result<Vec<u8>> {
+    let encoded = jwk[field]
+        .as_str()
+        .with_context(|| format!("JWK is missing \"{}\"", field))?;
+    URL_SAFE_NO_PAD
+        .decode(encoded)
+        .with_context(|| format!("JWK \"{}\" is not base64url", field))
+}
+
+/// Public key from an EC (P-256/P-384) or RSA JWK.
+fn jwk_public_key(jwk: &Value) -> Result<PKey<Public>> {
+    match jwk["kty"].as_str() {
+        Some("EC") => {
+            let nid = match jwk["crv"].as_str() {
+                Some("P-256") => Nid::X9_62_PRIME256V1,
+                Some("P-384") => Nid::SECP384R1,
+                other => anyhow::bail!("Unsupported EC curve: {:?}", other),
+            };
+            let group = EcGroup::from_curve_name(nid)?;
+            let x = BigNum::from_slice(&decode_b64_field(jwk, "x")?)?;
+            let y = BigNum::from_slice(&decode_b64_field(jwk, "y")?)?;
+            let key = EcKey::from_public_key_affine_coordinates(&group, &x, &y)
+                .context("Invalid EC public key")?;
+            Ok(PKey::from_ec_key(key)?)
+        }
+        Some("RSA") => {
+            let n = BigNum::from_slice(&decode_b64_field(jwk, "n")?)?;
+            let e = BigNum::from_slice(&decode_b64_field(jwk, "e")?)?;
+            Ok(PKey::from_rsa(Rsa::from_public_components(n, e)?)?)
+        }
+        other => anyhow::bail!("Unsupported JWK key type: {:?}", other),
+    }
+}
+
+/// RFC 7638 JWK thumbprint (base64url SHA-256 of the canonical required members).
+fn jwk_thumbprint(jwk: &Value) -> Result<String> {
+    let field = |name: &str| -> Result<String> {
+        let value = jwk[name]
+            .as_str()
+            .with_context(|| format!("JWK is missing \"{}\"", name))?;
+        Ok(serde_json::to_string(value)?)
+    };
+    let canonical = match jwk["kty"].as_str() {
+        Some("EC") => format!(
+            "{{\"crv\":{},\"kty\":\"EC\",\"x\":{},\"y\":{}}}",
+            field("crv")?,
+            field("x")?,
+            field("y")?
+        ),
+        Some("RSA") => format!(
+            "{{\"e\":{},\"kty\":\"RSA\",\"n\":{}}}",
+            field("e")?,
+            field("n")?
+        ),
+        other => anyhow::bail!("Unsupported JWK key type: {:?}", other),
+    };
+    Ok(URL_SAFE_NO_PAD.encode(openssl::sha::sha256(canonical.as_bytes())))
+}
+
+/// Verify a JWS signature. ECDSA signatures are the raw `r || s` form used by JWS.
+fn verify_signature(alg: &str, key: &PKey<Public>, data: &[u8], signature: &[u8]) -> Result<bool> {
+    let (digest, key_id, ec_size) = match alg {
+        "RS256" => (MessageDigest::sha256(), Id::RSA, None),
+        "ES256" => (MessageDigest::sha256(), Id::EC, Some(32)),
+        "ES384" => (MessageDigest::sha384(), Id::EC, Some(48)),
+        other => anyhow::bail!("Unsupported JWS algorithm: {}", other),
+    };
+    if key.id() != key_id {
+        anyhow::bail!("Key type does not match JWS algorithm {}", alg);
+    }
+
+    let der_signature;
+    let signature = match ec_size {
+        Some(size) => {
+            if signature.len() != size * 2 {
+                return Ok(false);
+            }
+            let r = BigNum::from_slice(&signature[..size])?;
+            let s = BigNum::from_slice(&signature[size..])?;
+            der_signature = EcdsaSig::from_private_components(r, s)?.to_der()?;
+            der_signature.as_slice()
+        }
+        None => signature,
+    };
+
+    let mut verifier = Verifier::new(digest, key)?;
+    verifier.update(data)?;
+    Ok(verifier.verify(signature).unwrap_or(false))
+}
+
+/// Body of a 200 response to a plain HTTP/1.1 request (identity or chunked encoding).
+fn parse_http_response(response: &[u8]) -> Result<String> {
+    let text = String::from_utf8_lossy(response);
+    let (head, body) = text
+        .split_once("\r\n\r\n")
+        .context("Malformed HTTP response to the challenge request")?;
+    let status_line = head.lines().next().unwrap_or_default();
+    let status = status_line.split_whitespace().nth(1).unwrap_or_default();
+    if status != "200" {
+        anyhow::bail!("Challenge request returned \"{}\"", status_line);
+    }
+
+    let chunked = head.lines().any(|line| {
+        line.to_ascii_lowercase().starts_with("transfer-encoding:")
+            && line.to_ascii_lowercase().contains("chunked")
+    });
+    if !chunked {
+        return Ok(body.to_string());
+    }
+
+    let mut decoded = String::new();
+    let mut rest = body;
+    while let Some((size_line, tail)) = rest.split_once("\r\n") {
+        let size = usize::from_str_radix(size_line.split(';').next().unwrap_or("").trim(), 16)
+            .context("Malformed chunked challenge response")?;
+        if size == 0 || tail.len() < size {
+            break;
+        }
+        decoded.push_str(&tail[..size]);
+        rest = tail[size..].trim_start_matches("\r\n");
+    }
+    Ok(decoded)
+}
+
+/// Path part of an absolute URL (`https://host:port/path` -> `/path`).
+fn url_path(url: &str) -> &str {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    without_scheme
+        .find('/')
+        .map_or("/", |i| &without_scheme[i..])
+}
+
+fn random_id() -> String {
+    let mut bytes = [0u8; 16];
+    openssl::rand::rand_bytes(&mut bytes).expect("OpenSSL RNG failure");
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Account ids are JWK thumbprints (base64url), which makes them safe as file names.
+fn is_safe_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn rfc3339(time: OffsetDateTime) -> String {
+    time.format(&Rfc3339).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::pkey::Private;
+    use openssl::sign::Signer;
+
+    /// A server keeping its certificates and accounts in a fresh directory, removed when the
+    /// returned `TempDir` is dropped.
+    fn server() -> (AcmeServer, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let accounts_dir = dir.path().join("acme-accounts");
+        crate::certs::create_private_dir(&accounts_dir).unwrap();
+        let server = AcmeServer {
+            config: Arc::new(Config::default()),
+            cert_manager: Arc::new(CertManager::new(dir.path(), "Test CA".to_string())),
+            host: "acme.test".to_string(),
+            auto_approve: false,
+            cert_days: 30,
+            accounts_dir,
+            state: Mutex::new(State::default()),
+        };
+        (server, dir)
+    }
+
+    /// A server for route hosts app.test and *.apps.test with a fresh CA, constrained to
+    /// `name_constraints` if any, and an account "account" to order with.
+    fn order_server(name_constraints: &[&str]) -> (AcmeServer, tempfile::TempDir) {
+        let (mut server, dir) = server();
+        let cert_manager = CertManager::new(dir.path(), "Test CA".to_string())
+            .with_name_constraints(name_constraints.iter().map(|s| s.to_string()).collect());
+        cert_manager.init().unwrap();
+        server.cert_manager = Arc::new(cert_manager);
+
+        let route = |host: &str| crate::config::Route {
+            host: host.to_string(),
+            port: 443,
+            listen_tls: true,
+            backend: "127.0.0.1".to_string(),
+            backend_port: 3000,
+            backend_tls: false,
+            client_auth: Default::default(),
+            backend_tls_options: None,
+            bind_address: None,
+        };
+        server.config = Arc::new(Config {
+            routes: vec![route("app.test"), route("*.apps.test")],
+            ..Config::default()
+        });
+
+        let (key, _) = ec_key();
+        let key = PKey::public_key_from_pem(&key.public_key_to_pem().unwrap()).unwrap();
+        server.state.lock().unwrap().accounts.insert(
+            "account".to_string(),
+            Account {
+                key,
+                thumbprint: "account".to_string(),
+                contact: Vec::new(),
+            },
+        );
+        (server, dir)
+    }
+
+    /// Order a certificate for `name` from the "account" account.
+    fn order(server: &AcmeServer, name: &str) -> AcmeResult<AcmeResponse> {
+        let key = server.state.lock().unwrap().accounts["account"].key.clone();
+        let request = SignedRequest {
+            jwk: None,
+            key,
+            account: Some("account".to_string()),
+            payload: Some(json!({ "identifiers": [{ "type": "dns", "value": name }] })),
+        };
+        server.new_order(&request, "https://acme.test")
+    }
+
+    /// A new P-256 key and its JWK.
+    fn ec_key() -> (PKey<Private>, Value) {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = EcKey::generate(&group).unwrap();
+        let mut ctx = openssl::bn::BigNumContext::new().unwrap();
+        let (mut x, mut y) = (BigNum::new().unwrap(), BigNum::new().unwrap());
+        key.public_key()
+            .affine_coordinates(&group, &mut x, &mut y, &mut ctx)
+            .unwrap();
+        let jwk = json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "x": URL_SAFE_NO_PAD.encode(x.to_vec_padded(32).unwrap()),
+            "y": URL_SAFE_NO_PAD.encode(y.to_vec_padded(32).unwrap()),
+        });
+        (PKey::from_ec_key(key).unwrap(), jwk)
+    }
+
+    /// A flattened JWS signed with ES256; `payload` is the raw payload JSON ("" for POST-as-GET).
+    fn sign(key: &PKey<Private>, protected: &Value, payload: &str) -> Vec<u8> {
+        let protected = URL_SAFE_NO_PAD.encode(protected.to_string());
+        let payload = URL_SAFE_NO_PAD.encode(payload);
+        let mut signer = Signer::new(MessageDigest::sha256(), key).unwrap();
+        signer
+            .update(format!("{}.{}", protected, payload).as_bytes())
+            .unwrap();
+        let der = EcdsaSig::from_der(&signer.sign_to_vec().unwrap()).unwrap();
+        let mut signature = der.r().to_vec_padded(32).unwrap();
+        signature.extend(der.s().to_vec_padded(32).unwrap());
+        json!({
+            "protected": protected,
+            "payload": payload,
+            "signature": URL_SAFE_NO_PAD.encode(signature),
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    /// Status of an accepted request.
+    fn accepted(result: AcmeResult<AcmeResponse>) -> u16 {
+        match result {
+            Ok(response) => response.status,
+            Err(response) => panic!("rejected: {}", String::from_utf8_lossy(&response.body)),
+        }
+    }
+
+    /// Status and error type of a rejected request.
+    fn rejection<T>(result: AcmeResult<T>) -> (u16, String) {
+        let Err(response) = result else {
+            panic!("request was accepted");
+        };
+        let body: Value = serde_json::from_slice(&response.body).unwrap();
+        let kind = body["type"]
+            .as_str()
+            .unwrap()
+            .trim_start_matches(ERROR_PREFIX);
+        (response.status, kind.to_string())
+    }
+
+    #[test]
+    fn verify_jws_accepts_signed_request_once() {
+        let (server, _dir) = server();
+        let (key, jwk) = ec_key();
+        let protected = json!({
+            "alg": "ES256",
+            "nonce": server.new_nonce(),
+            "url": "https://acme.test/new-account",
+            "jwk": jwk,
+        });
+        let body = sign(&key, &protected, r#"{"termsOfServiceAgreed":true}"#);
+
+        let request = server.verify_jws(&body, "/new-account").ok().unwrap();
+        assert_eq!(request.jwk, Some(jwk));
+        assert_eq!(request.account, None);
+        assert_eq!(
+            request.payload,
+            Some(json!({ "termsOfServiceAgreed": true }))
+        );
+
+        // The nonce is used up
+        assert_eq!(
+            rejection(server.verify_jws(&body, "/new-account")),
+            (400, "badNonce".to_string())
+        );
+    }
+
+    #[test]
+    fn verify_jws_reads_empty_payload_as_post_as_get() {
+        let (server, _dir) = server();
+        let (key, jwk) = ec_key();
+        let protected = json!({
+            "alg": "ES256",
+            "nonce": server.new_nonce(),
+            "url": "https://acme.test/new-account",
+            "jwk": jwk,
+        });
+        let body = sign(&key, &protected, "");
+        let request = server.verify_jws(&body, "/new-account").ok().unwrap();
+        assert!(request.payload.is_none());
+    }
+
+    #[test]
+    fn verify_jws_rejects_bad_requests() {
+        let (server, _dir) = server();
+        let (key, jwk) = ec_key();
+        let header = |nonce: String, url: &str| json!({ "alg": "ES256", "nonce": nonce, "url": url, "jwk": jwk });
+
+        let unknown_nonce = sign(
+            &key,
+            &header(random_id(), "https://acme.test/new-order"),
+            "{}",
+        );
+        assert_eq!(
+            rejection(server.verify_jws(&unknown_nonce, "/new-order")),
+            (400, "badNonce".to_string())
+        );
+
+        // Only the path is compared, so another host name is fine but another path is not
+        let other_host = sign(
+            &key,
+            &header(server.new_nonce(), "https://10.0.0.1:8443/new-order"),
+            "{}",
+        );
+        assert!(server.verify_jws(&other_host, "/new-order").is_ok());
+        let other_path = sign(
+            &key,
+            &header(server.new_nonce(), "https://acme.test/new-account"),
+            "{}",
+        );
+        assert_eq!(
+            rejection(server.verify_jws(&other_path, "/new-order")),
+            (401, "unauthorized".to_string())
+        );
+
+        let mut tampered: Value = serde_json::from_slice(&sign(
+            &key,
+            &header(server.new_nonce(), "https://acme.test/new-order"),
+            "{}",
+        ))
+        .unwrap();
+        tampered["payload"] = json!(URL_SAFE_NO_PAD.encode(r#"{"identifiers":[]}"#));
+        assert_eq!(
+            rejection(server.verify_jws(tampered.to_string().as_bytes(), "/new-order")),
+            (401, "unauthorized".to_string())
+        );
+
+        let (other_key, _) = ec_key();
+        let wrong_key = sign(
+            &other_key,
+            &header(server.new_nonce(), "https://acme.test/new-order"),
+            "{}",
+        );
+        assert_eq!(
+            rejection(server.verify_jws(&wrong_key, "/new-order")),
+            (401, "unauthorized".to_string())
+        );
+
+        let unknown_account = json!({
+            "alg": "ES256",
+            "nonce": server.new_nonce(),
+            "url": "https://acme.test/new-order",
+            "kid": "https://acme.test/acct/nobody",
+        });
+        assert_eq!(
+            rejection(server.verify_jws(&sign(&key, &unknown_account, "{}"), "/new-order")),
+            (400, "accountDoesNotExist".to_string())
+        );
+
+        let both = json!({
+            "alg": "ES256",
+            "nonce": server.new_nonce(),
+            "url": "https://acme.test/new-order",
+            "jwk": jwk,
+            "kid": "https://acme.test/acct/nobody",
+        });
+        assert_eq!(
+            rejection(server.verify_jws(&sign(&key, &both, "{}"), "/new-order")),
+            (400, "malformed".to_string())
+        );
+
+        let rs256 = json!({
+            "alg": "RS256",
+            "nonce": server.new_nonce(),
+            "url": "https://acme.test/new-order",
+            "jwk": jwk,
+        });
+        assert_eq!(
+            rejection(server.verify_jws(&sign(&key, &rs256, "{}"), "/new-order")),
+            (400, "badSignatureAlgorithm".to_string())
+        );
+
+        assert_eq!(
+            rejection(server.verify_jws(b"not json", "/new-order")),
+            (400, "malformed".to_string())
+        );
+    }
+
+    #[test]
+    fn unconstrained_ca_only_orders_route_hosts() {
+        let (server, _dir) = order_server(&[]);
+        assert_eq!(accepted(order(&server, "app.test")), 201);
+        assert_eq!(accepted(order(&server, "x.apps.test")), 201);
+        for name in ["example.com", "other.test", "*.example.com"] {
+            assert_eq!(
+                rejection(order(&server, name)),
+                (400, "rejectedIdentifier".to_string()),
+                "{}",
+                name
+            );
+        }
+
+        // Nothing is fetched for other names either
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let error = runtime
+            .block_on(server.fetch_http01("example.com", "token"))
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("neither a route host"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn constrained_ca_orders_names_under_its_constraints() {
+        let (server, _dir) = order_server(&["test"]);
+        assert_eq!(accepted(order(&server, "app.test")), 201);
+        assert_eq!(accepted(order(&server, "other.test")), 201);
+        assert_eq!(
+            rejection(order(&server, "example.com")),
+            (400, "rejectedIdentifier".to_string())
+        );
+    }
+
+    #[test]
+    fn oldest_nonces_are_dropped() {
+        let (server, _dir) = server();
+        let first = server.new_nonce();
+        let second = server.new_nonce();
+        for _ in 0..MAX_NONCES - 1 {
+            server.new_nonce();
+        }
+        let state = server.state.lock().unwrap();
+        assert_eq!(state.nonces.len(), MAX_NONCES);
+        assert!(!state.nonces.contains(&first));
+        assert!(state.nonces.contains(&second));
+    }
+
+    #[test]
+    fn expired_orders_are_removed_with_their_state() {
+        let now = OffsetDateTime::now_utc();
+        let mut state = State::default();
+        for (id, expires) in [
+            ("old", now - time::Duration::hours(1)),
+            ("new", now + time::Duration::hours(1)),
+        ] {
+            state.orders.insert(
+                id.to_string(),
+                Order {
+                    account: "account".to_string(),
+                    status: "valid",
+                    expires,
+                    identifiers: vec![format!("{}.test", id)],
+                    authorizations: vec![format!("{}-authz", id)],
+                    certificate: Some(format!("{}-cert", id)),
+                },
+            );
+            state.authorizations.insert(
+                format!("{}-authz", id),
+                Authorization {
+                    identifier: format!("{}.test", id),
+                    wildcard: false,
+                    status: "valid",
+                    expires,
+                    challenge: format!("{}-chall", id),
+                    token: "token".to_string(),
+                    account_thumbprint: "account".to_string(),
+                    error: None,
+                },
+            );
+            state
+                .challenges
+                .insert(format!("{}-chall", id), format!("{}-authz", id));
+            state
+                .certificates
+                .insert(format!("{}-cert", id), "PEM".to_string());
+        }
+
+        remove_expired_orders(&mut state, now);
+
+        assert_eq!(state.orders.keys().collect::<Vec<_>>(), ["new"]);
+        assert_eq!(
+            state.authorizations.keys().collect::<Vec<_>>(),
+            ["new-authz"]
+        );
+        assert_eq!(state.challenges.keys().collect::<Vec<_>>(), ["new-chall"]);
+        assert_eq!(state.certificates.keys().collect::<Vec<_>>(), ["new-cert"]);
+    }
+
+    #[test]
+    fn jwk_thumbprint_matches_rfc7638_example() {
+        // RFC 7638 section 3.1; members outside the required set are ignored
+        let jwk = json!({
+            "kty": "RSA",
+            "n": "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw",
+            "e": "AQAB",
+            "alg": "RS256",
+            "kid": "2011-04-29",
+        });
+        assert_eq!(
+            jwk_thumbprint(&jwk).unwrap(),
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+        assert!(jwk_thumbprint(&json!({ "kty": "oct", "k": "c2VjcmV0" })).is_err());
+        assert!(jwk_thumbprint(&json!({ "kty": "EC", "crv": "P-256", "x": "AA" })).is_err());
+    }
+
+    #[test]
+    fn parse_http_response_reads_body() {
+        assert_eq!(
+            parse_http_response(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\ntoken").unwrap(),
+            "token"
+        );
+        assert_eq!(
+            parse_http_response(
+                b"HTTP/1.1 200 OK\r\nTransfer-Encoding: Chunked\r\n\r\n3;ext=1\r\ntok\r\n2\r\nen\r\n0\r\n\r\n"
+            )
+            .unwrap(),
+            "token"
+        );
+        let error = parse_http_response(b"HTTP/1.1 404 Not Found\r\n\r\nnope").unwrap_err();
+        assert!(error.to_string().contains("404 Not Found"));
+        assert!(parse_http_response(b"HTTP/1.1 200 OK\r\n").is_err());
+        assert!(
+            parse_http_response(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn url_path_strips_scheme_and_authority() {
+        assert_eq!(url_path("https://acme.test/new-order"), "/new-order");
+        assert_eq!(url_path("https://acme.test:8443/acct/abc"), "/acct/abc");
+        assert_eq!(
+            url_path("https://[::1]:8443/order/x/finalize"),
+            "/order/x/finalize"
+        );
+        assert_eq!(url_path("https://acme.test"), "/");
+        assert_eq!(url_path("/chall/x"), "/chall/x");
+    }
+}
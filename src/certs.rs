@@ -1,43 +1,192 @@
 use anyhow::{Context, Result};
+use openssl::asn1::Asn1Time;
+use openssl::pkey::PKey;
+use openssl::symm::Cipher;
+use openssl::x509::X509;
 use rcgen::{
-    BasicConstraints, CertificateParams, DistinguishedName, DnType, IsCa, KeyPair,
+    BasicConstraints, Certificate, CertificateParams, CertificateSigningRequestParams, CidrSubnet,
+    DistinguishedName, DnType,
+    ExtendedKeyUsagePurpose, GeneralSubtree, Ia5String, IsCa, KeyPair, NameConstraints, SanType,
 };
 use std::fs;
+use std::io::IsTerminal;
+use std::net::IpAddr;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use time::{Duration, OffsetDateTime};
 
+/// Environment variable holding the passphrase for an encrypted CA key.
+pub const CA_PASSPHRASE_ENV: &str = "DEVRELAY_CA_PASSPHRASE";
+
+/// IP ranges a name-constrained CA may still sign for: loopback and private networks.
+const PERMITTED_IP_RANGES: &[&str] = &[
+    "127.0.0.0/8",
+    "10.0.0.0/8",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "::1/128",
+    "fc00::/7",
+];
+
 pub struct CertManager {
     cert_dir: PathBuf,
+    ca_dir: PathBuf,
+    external_ca: Option<(PathBuf, PathBuf)>,
     ca_name: String,
+    name_constraints: Vec<String>,
+    encrypt_ca_key: bool,
+    ca_passphrase: OnceLock<String>,
+    leaf_lifetime: Option<Duration>,
 }
 
 impl CertManager {
     pub fn new(cert_dir: impl AsRef<Path>, ca_name: String) -> Self {
         Self {
             cert_dir: cert_dir.as_ref().to_path_buf(),
+            ca_dir: cert_dir.as_ref().to_path_buf(),
+            external_ca: None,
             ca_name,
+            name_constraints: Vec::new(),
+            encrypt_ca_key: false,
+            ca_passphrase: OnceLock::new(),
+            leaf_lifetime: None,
         }
     }
 
+    /// Keep the CA in `ca_dir` (e.g. a per-user directory shared by all projects)
+    /// while leaf certificates stay in `cert_dir`.
+    pub fn with_ca_dir(mut self, ca_dir: impl AsRef<Path>) -> Self {
+        self.ca_dir = ca_dir.as_ref().to_path_buf();
+        self
+    }
+
+    /// Sign leaf certificates with an existing CA (e.g. mkcert's rootCA.pem / rootCA-key.pem)
+    /// instead of generating one. The caller is expected to already trust it.
+    pub fn with_external_ca(mut self, cert_path: PathBuf, key_path: PathBuf) -> Self {
+        self.external_ca = Some((cert_path, key_path));
+        self
+    }
+
+    pub fn is_external_ca(&self) -> bool {
+        self.external_ca.is_some()
+    }
+
+    /// Restrict a newly generated CA to the given DNS suffixes (e.g. `test`, `localhost`).
+    /// Has no effect on a CA that already exists on disk.
+    pub fn with_name_constraints(mut self, suffixes: Vec<String>) -> Self {
+        self.name_constraints = suffixes;
+        self
+    }
+
+    /// Store the CA key as passphrase-encrypted PKCS#8 (passphrase from `DEVRELAY_CA_PASSPHRASE` or a prompt).
+    pub fn with_encrypted_ca_key(mut self, encrypt: bool) -> Self {
+        self.encrypt_ca_key = encrypt;
+        self
+    }
+
+    /// Issue server certificates valid for `lifetime` instead of a year (short-lived mode).
+    /// They are renewed once two thirds of the lifetime have passed.
+    pub fn with_leaf_lifetime(mut self, lifetime: Option<std::time::Duration>) -> Self {
+        self.leaf_lifetime = lifetime.and_then(|l| Duration::try_from(l).ok());
+        self
+    }
+
+    /// How often short-lived server certificates need re-issuing, if enabled.
+    pub fn leaf_rotation_interval(&self) -> Option<std::time::Duration> {
+        let lifetime = self.leaf_lifetime?;
+        std::time::Duration::try_from(lifetime * 2 / 3).ok()
+    }
+
+    fn leaf_not_after(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc() + self.leaf_lifetime.unwrap_or(Duration::days(365))
+    }
+
+    /// Whether a certificate must be re-issued: it has expired (e.g. left over from short-lived
+    /// mode) or, in short-lived mode, has passed half of its lifetime (rotation runs after two
+    /// thirds) or outlives the lifetime (e.g. a 1-year cert).
+    fn is_due_for_renewal(&self, cert_path: &Path) -> Result<bool> {
+        let cert = X509::from_pem(&fs::read(cert_path).context("Failed to read certificate")?)
+            .context("Failed to parse certificate")?;
+        let now = Asn1Time::days_from_now(0)?;
+        let remaining = now.diff(cert.not_after())?;
+        let remaining = Duration::days(remaining.days.into()) + Duration::seconds(remaining.secs.into());
+        Ok(match self.leaf_lifetime {
+            Some(lifetime) => remaining <= lifetime / 2 || remaining > lifetime,
+            None => remaining <= Duration::ZERO,
+        })
+    }
+
     pub fn init(&self) -> Result<()> {
         fs::create_dir_all(&self.cert_dir)
             .with_context(|| format!("Failed to create cert directory: {}", self.cert_dir.display()))?;
 
+        // Keep private keys out of version control when cert_dir lives inside a project (the
+        // default ./certs does). Only written when missing, so it can be edited or replaced.
+        let gitignore = self.cert_dir.join(".gitignore");
+        if !gitignore.exists() {
+            fs::write(&gitignore, "*\n").context("Failed to write cert directory .gitignore")?;
+        }
+
+        if let Some((ca_cert_path, ca_key_path)) = &self.external_ca {
+            for path in [ca_cert_path, ca_key_path] {
+                if !path.exists() {
+                    anyhow::bail!("External CA file not found: {}", path.display());
+                }
+            }
+            // Not ours to change, so only warn
+            check_key_permissions(ca_key_path, false)?;
+            println!("Using external CA: {}", ca_cert_path.display());
+            if !self.name_constraints.is_empty() {
+                println!("⚠️  name_constraints only apply to generated CAs and are ignored for an external CA.");
+            }
+            return Ok(());
+        }
+
+        if self.ca_dir != self.cert_dir {
+            create_private_dir(&self.ca_dir)?;
+
+            let legacy_ca = self.cert_dir.join("ca.crt");
+            if legacy_ca.exists() {
+                println!(
+                    "ℹ️  Ignoring per-project CA at {} in favour of the shared CA in {}.",
+                    legacy_ca.display(),
+                    self.ca_dir.display()
+                );
+                println!("   Set `tls.ca_dir` to the project's cert_dir to keep using it.");
+            }
+        }
+
         // Generate CA if it doesn't exist
         if !self.ca_cert_path().exists() {
             println!("Generating new Certificate Authority...");
-            self.generate_ca()?;
+            self.generate_ca(&self.ca_cert_path(), &self.ca_key_path())?;
             println!("✓ CA certificate generated at: {}", self.ca_cert_path().display());
             println!("\nTo trust this CA manually:");
             println!("  macOS: sudo security add-trusted-cert -d -r trustRoot -k /Library/Keychains/System.keychain {}", self.ca_cert_path().display());
             println!("  Linux (Debian/Ubuntu): sudo cp {} /usr/local/share/ca-certificates/devrelay-ca.crt && sudo update-ca-certificates", self.ca_cert_path().display());
             println!("  Linux (RHEL/Fedora):   sudo cp {} /etc/pki/ca-trust/source/anchors/devrelay-ca.crt && sudo update-ca-trust", self.ca_cert_path().display());
+        } else {
+            check_key_permissions(&self.ca_key_path(), true)?;
+
+            if self.encrypt_ca_key && !self.is_ca_key_encrypted()? {
+                println!("Encrypting existing CA key...");
+                let ca_key_pair = self.read_ca_key()?;
+                self.write_ca_key(&ca_key_pair, &self.ca_key_path())?;
+                println!("✓ CA key encrypted at: {}", self.ca_key_path().display());
+            }
+
+            if !self.name_constraints.is_empty() && self.ca_name_constraints()?.is_none() {
+                println!(
+                    "⚠️  Existing CA at {} has no name constraints. Delete it to generate a constrained one.",
+                    self.ca_cert_path().display()
+                );
+            }
         }
 
         Ok(())
     }
 
-    fn generate_ca(&self) -> Result<()> {
+    fn generate_ca(&self, cert_path: &Path, key_path: &Path) -> Result<()> {
         let mut params = CertificateParams::default();
         params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
 
@@ -49,64 +198,108 @@ impl CertManager {
         params.not_before = OffsetDateTime::now_utc();
         params.not_after = OffsetDateTime::now_utc() + Duration::days(365 * 10); // 10 years
 
+        if !self.name_constraints.is_empty() {
+            params.name_constraints = Some(self.build_name_constraints()?);
+            println!("  Name constraints: {}", self.name_constraints.join(", "));
+        }
+
         let ca_key_pair = KeyPair::generate()?;
         let ca_cert = params.self_signed(&ca_key_pair)
             .context("Failed to generate CA certificate")?;
 
-        let ca_cert_pem = ca_cert.pem();
-        let ca_key_pem = ca_key_pair.serialize_pem();
-
-        fs::write(self.ca_cert_path(), ca_cert_pem)
+        fs::write(cert_path, ca_cert.pem())
             .context("Failed to write CA certificate")?;
-        fs::write(self.ca_key_path(), ca_key_pem)
-            .context("Failed to write CA key")?;
+        self.write_ca_key(&ca_key_pair, key_path)?;
 
         Ok(())
     }
 
+    /// Generate a new CA and move the current one aside. The previous CA is kept as
+    /// `ca.old.crt`/`ca.old.key` until `remove_old_ca`, so both can be trusted while leaf certs
+    /// are re-issued. The new CA is written to `ca.new.*` first and swapped in afterwards; if
+    /// any step fails, the current CA is put back.
+    pub fn rotate_ca(&self) -> Result<()> {
+        if self.is_external_ca() {
+            anyhow::bail!("The CA is external (tls.ca_cert); rotate it with the tool that created it");
+        }
+        if self.old_ca_cert_path().exists() {
+            anyhow::bail!(
+                "A previous CA is still kept at {}; run `devrelay ca rotate --finish` first",
+                self.old_ca_cert_path().display()
+            );
+        }
+
+        let new_cert_path = self.ca_dir.join("ca.new.crt");
+        let new_key_path = self.ca_dir.join("ca.new.key");
+        let remove_new = || {
+            let _ = fs::remove_file(&new_cert_path);
+            let _ = fs::remove_file(&new_key_path);
+        };
+
+        println!("Generating new Certificate Authority...");
+        if let Err(e) = self.generate_ca(&new_cert_path, &new_key_path) {
+            remove_new();
+            return Err(e);
+        }
+
+        let renames = [
+            (self.ca_cert_path(), self.old_ca_cert_path()),
+            (self.ca_key_path(), self.old_ca_key_path()),
+            (new_cert_path.clone(), self.ca_cert_path()),
+            (new_key_path.clone(), self.ca_key_path()),
+        ];
+        for (done, (from, to)) in renames.iter().enumerate() {
+            if let Err(e) = fs::rename(from, to) {
+                // Undo in reverse order, so the current CA ends up back in place
+                for (from, to) in renames[..done].iter().rev() {
+                    let _ = fs::rename(to, from);
+                }
+                remove_new();
+                return Err(e).with_context(|| {
+                    format!("Failed to move {} to {}", from.display(), to.display())
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete the CA kept by `rotate_ca`.
+    pub fn remove_old_ca(&self) -> Result<()> {
+        for path in [self.old_ca_cert_path(), self.old_ca_key_path()] {
+            if path.exists() {
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn generate_server_cert(&self, domain: &str) -> Result<()> {
         let cert_path = self.server_cert_path(domain);
         let key_path = self.server_key_path(domain);
 
-        if cert_path.exists() && key_path.exists() {
+        if cert_path.exists()
+            && key_path.exists()
+            && self.is_issued_by_ca(&cert_path)?
+            && !self.is_due_for_renewal(&cert_path)?
+        {
             return Ok(()); // Already exists
         }
 
         println!("Generating server certificate for: {}", domain);
 
-        // Load CA key
-        let ca_key_pem = fs::read_to_string(self.ca_key_path())
-            .context("Failed to read CA key")?;
-
-        let ca_key_pair = KeyPair::from_pem(&ca_key_pem)
-            .context("Failed to parse CA key")?;
-
-        // Reconstruct CA params (they need to match what was used to create the CA)
-        let mut ca_params = CertificateParams::default();
-        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
-
-        let mut dn = DistinguishedName::new();
-        dn.push(DnType::CommonName, &self.ca_name);
-        dn.push(DnType::OrganizationName, "DevRelay");
-        ca_params.distinguished_name = dn;
-
-        // Create CA certificate object for signing
-        let ca_cert = ca_params.self_signed(&ca_key_pair)
-            .context("Failed to reconstruct CA certificate")?;
+        let (ca_cert, ca_key_pair) = self.load_ca()?;
 
         // Create server cert
         let mut params = CertificateParams::default();
-        params.subject_alt_names = vec![
-            rcgen::SanType::DnsName(rcgen::Ia5String::try_from(domain.to_string())
-                .context("Invalid domain name")?),
-        ];
+        params.subject_alt_names = vec![subject_alt_name(domain)?];
 
         let mut dn = DistinguishedName::new();
         dn.push(DnType::CommonName, domain);
         params.distinguished_name = dn;
 
         params.not_before = OffsetDateTime::now_utc();
-        params.not_after = OffsetDateTime::now_utc() + Duration::days(365); // 1 year
+        params.not_after = self.leaf_not_after(); // 1 year unless short-lived
 
         let server_key_pair = KeyPair::generate()?;
         let server_cert = params.signed_by(&server_key_pair, &ca_cert, &ca_key_pair)
@@ -117,7 +310,7 @@ impl CertManager {
 
         fs::write(&cert_path, server_cert_pem)
             .context("Failed to write server certificate")?;
-        fs::write(&key_path, server_key_pem)
+        write_private_key(&key_path, server_key_pem)
             .context("Failed to write server key")?;
 
         println!("✓ Certificate generated for: {}", domain);
@@ -125,7 +318,8 @@ impl CertManager {
         Ok(())
     }
 
-    /// Generate a single server certificate covering all given domains as SANs.
+    /// Generate a single server certificate covering all given domains as SANs
+    /// (IP address literals become IP SANs).
     /// Used for TLS listeners that may serve multiple domains on the same port.
     /// Always regenerated on startup to pick up config changes.
     pub fn generate_combined_server_cert(&self, domains: &[String]) -> Result<()> {
@@ -134,32 +328,13 @@ impl CertManager {
 
         println!("Generating combined server certificate for TLS listeners...");
 
-        // Load CA key
-        let ca_key_pem = fs::read_to_string(self.ca_key_path())
-            .context("Failed to read CA key")?;
-        let ca_key_pair = KeyPair::from_pem(&ca_key_pem)
-            .context("Failed to parse CA key")?;
-
-        // Reconstruct CA for signing
-        let mut ca_params = CertificateParams::default();
-        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
-        let mut dn = DistinguishedName::new();
-        dn.push(DnType::CommonName, &self.ca_name);
-        dn.push(DnType::OrganizationName, "DevRelay");
-        ca_params.distinguished_name = dn;
-        let ca_cert = ca_params.self_signed(&ca_key_pair)
-            .context("Failed to reconstruct CA certificate")?;
+        let (ca_cert, ca_key_pair) = self.load_ca()?;
 
         // Create server cert with all domains as SANs
         let mut params = CertificateParams::default();
         params.subject_alt_names = domains
             .iter()
-            .map(|d| {
-                Ok(rcgen::SanType::DnsName(
-                    rcgen::Ia5String::try_from(d.clone())
-                        .map_err(|_| anyhow::anyhow!("Invalid domain name: {}", d))?,
-                ))
-            })
+            .map(|d| subject_alt_name(d))
             .collect::<Result<Vec<_>>>()?;
 
         let mut dn = DistinguishedName::new();
@@ -167,7 +342,7 @@ impl CertManager {
         params.distinguished_name = dn;
 
         params.not_before = OffsetDateTime::now_utc();
-        params.not_after = OffsetDateTime::now_utc() + Duration::days(365);
+        params.not_after = self.leaf_not_after();
 
         let server_key_pair = KeyPair::generate()?;
         let server_cert = params.signed_by(&server_key_pair, &ca_cert, &ca_key_pair)
@@ -175,7 +350,7 @@ impl CertManager {
 
         fs::write(&cert_path, server_cert.pem())
             .context("Failed to write combined server certificate")?;
-        fs::write(&key_path, server_key_pair.serialize_pem())
+        write_private_key(&key_path, server_key_pair.serialize_pem())
             .context("Failed to write combined server key")?;
 
         for domain in domains {
@@ -185,6 +360,257 @@ impl CertManager {
         Ok(())
     }
 
+    /// Issue a certificate for arbitrary DNS names, IP addresses or wildcards, valid for `days`.
+    /// Returns the certificate and private key as PEM without writing anything to cert_dir.
+    pub fn issue_cert(&self, names: &[String], days: i64) -> Result<(String, String)> {
+        let (ca_cert, ca_key_pair) = self.load_ca()?;
+
+        let mut params = CertificateParams::default();
+        params.subject_alt_names = names
+            .iter()
+            .map(|n| subject_alt_name(n))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut dn = DistinguishedName::new();
+        dn.push(DnType::CommonName, names.first().context("At least one name is required")?);
+        params.distinguished_name = dn;
+
+        params.not_before = OffsetDateTime::now_utc();
+        params.not_after = OffsetDateTime::now_utc() + Duration::days(days);
+
+        let key_pair = KeyPair::generate()?;
+        let cert = params.signed_by(&key_pair, &ca_cert, &ca_key_pair)
+            .context("Failed to generate certificate")?;
+
+        Ok((cert.pem(), key_pair.serialize_pem()))
+    }
+
+    /// Issue a client certificate (for mTLS routes) identifying `name` as its common name.
+    /// Returns the certificate and private key PEMs.
+    pub fn issue_client_cert(&self, name: &str, days: i64) -> Result<(String, String)> {
+        let (ca_cert, ca_key_pair) = self.load_ca()?;
+
+        let mut params = CertificateParams::default();
+        let mut dn = DistinguishedName::new();
+        dn.push(DnType::CommonName, name);
+        dn.push(DnType::OrganizationName, "DevRelay");
+        params.distinguished_name = dn;
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+
+        params.not_before = OffsetDateTime::now_utc();
+        params.not_after = OffsetDateTime::now_utc() + Duration::days(days);
+
+        let key_pair = KeyPair::generate()?;
+        let cert = params.signed_by(&key_pair, &ca_cert, &ca_key_pair)
+            .context("Failed to generate client certificate")?;
+
+        Ok((cert.pem(), key_pair.serialize_pem()))
+    }
+
+    /// Sign a DER-encoded certificate signing request (e.g. from an ACME client) as a server
+    /// certificate for `names`, which replace whatever names the CSR asked for.
+    pub fn sign_csr(&self, csr_der: &[u8], names: &[String], days: i64) -> Result<String> {
+        let (ca_cert, ca_key_pair) = self.load_ca()?;
+
+        let mut csr = CertificateSigningRequestParams::from_der(&csr_der.to_vec().into())
+            .context("Invalid certificate signing request")?;
+        csr.params.subject_alt_names = names
+            .iter()
+            .map(|n| subject_alt_name(n))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut dn = DistinguishedName::new();
+        dn.push(DnType::CommonName, names.first().context("At least one name is required")?);
+        csr.params.distinguished_name = dn;
+        csr.params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+
+        csr.params.not_before = OffsetDateTime::now_utc();
+        csr.params.not_after = OffsetDateTime::now_utc() + Duration::days(days);
+
+        let cert = csr.signed_by(&ca_cert, &ca_key_pair)
+            .context("Failed to sign certificate request")?;
+        Ok(cert.pem())
+    }
+
+    /// Read the CA key once so an encryption passphrase is asked for now rather than
+    /// on first use (e.g. while serving a request).
+    pub fn unlock_ca_key(&self) -> Result<()> {
+        self.read_ca_key().map(|_| ())
+    }
+
+    pub fn ca_cert_pem(&self) -> Result<String> {
+        fs::read_to_string(self.ca_cert_path()).context("Failed to read CA certificate")
+    }
+
+    /// Load the CA key and rebuild the CA certificate from disk for signing.
+    /// Parsing the existing cert keeps the issuer name and key identifier identical to what is installed.
+    fn load_ca(&self) -> Result<(Certificate, KeyPair)> {
+        let ca_key_pair = self.read_ca_key()?;
+
+        let ca_cert_pem = fs::read_to_string(self.ca_cert_path())
+            .context("Failed to read CA certificate")?;
+        let ca_params = CertificateParams::from_ca_cert_pem(&ca_cert_pem)
+            .context("Failed to parse CA certificate")?;
+        let ca_cert = ca_params.self_signed(&ca_key_pair)
+            .context("Failed to reconstruct CA certificate")?;
+
+        Ok((ca_cert, ca_key_pair))
+    }
+
+    /// Whether the certificate at `cert_path` was signed by the current CA
+    /// (leaf certs left over from a previous or per-project CA are re-issued).
+    fn is_issued_by_ca(&self, cert_path: &Path) -> Result<bool> {
+        let ca_cert = X509::from_pem(&fs::read(self.ca_cert_path()).context("Failed to read CA certificate")?)
+            .context("Failed to parse CA certificate")?;
+        let cert = match fs::read(cert_path).ok().and_then(|pem| X509::from_pem(&pem).ok()) {
+            Some(cert) => cert,
+            None => return Ok(false),
+        };
+        let ca_public_key = ca_cert.public_key().context("Failed to read CA public key")?;
+        Ok(cert.verify(&ca_public_key).unwrap_or(false))
+    }
+
+    fn is_ca_key_encrypted(&self) -> Result<bool> {
+        let ca_key_pem = fs::read_to_string(self.ca_key_path())
+            .context("Failed to read CA key")?;
+        Ok(ca_key_pem.contains("BEGIN ENCRYPTED PRIVATE KEY"))
+    }
+
+    /// Read the CA key, refusing a managed key other users can read and decrypting encrypted
+    /// PKCS#8.
+    fn read_ca_key(&self) -> Result<KeyPair> {
+        let key_path = self.ca_key_path();
+        if !self.is_external_ca() {
+            check_key_permissions(&key_path, true)?;
+        }
+
+        let ca_key_pem = fs::read_to_string(&key_path)
+            .context("Failed to read CA key")?;
+        let ca_key_pem = if ca_key_pem.contains("BEGIN ENCRYPTED PRIVATE KEY") {
+            let passphrase = self.ca_passphrase(false)?;
+            let pkey = PKey::private_key_from_pem_passphrase(ca_key_pem.as_bytes(), passphrase.as_bytes())
+                .context("Failed to decrypt CA key (wrong passphrase?)")?;
+            String::from_utf8(pkey.private_key_to_pem_pkcs8()?)
+                .context("Failed to decode decrypted CA key")?
+        } else {
+            ca_key_pem
+        };
+
+        KeyPair::from_pem(&ca_key_pem).context("Failed to parse CA key")
+    }
+
+    fn write_ca_key(&self, ca_key_pair: &KeyPair, path: &Path) -> Result<()> {
+        let ca_key_pem = if self.encrypt_ca_key {
+            let passphrase = self.ca_passphrase(true)?;
+            let pkey = PKey::private_key_from_pkcs8(&ca_key_pair.serialize_der())
+                .context("Failed to load CA key for encryption")?;
+            pkey.private_key_to_pem_pkcs8_passphrase(Cipher::aes_256_cbc(), passphrase.as_bytes())
+                .context("Failed to encrypt CA key")?
+        } else {
+            ca_key_pair.serialize_pem().into_bytes()
+        };
+
+        write_private_key(path, ca_key_pem)
+            .context("Failed to write CA key")
+    }
+
+    /// Passphrase for the CA key, from `DEVRELAY_CA_PASSPHRASE` or prompted once per run.
+    fn ca_passphrase(&self, confirm: bool) -> Result<&str> {
+        if let Some(passphrase) = self.ca_passphrase.get() {
+            return Ok(passphrase);
+        }
+
+        let passphrase = match std::env::var(CA_PASSPHRASE_ENV) {
+            Ok(p) if !p.is_empty() => p,
+            _ => {
+                if !std::io::stdin().is_terminal() {
+                    anyhow::bail!(
+                        "The CA key is encrypted. Set {} to its passphrase.",
+                        CA_PASSPHRASE_ENV
+                    );
+                }
+                let passphrase = rpassword::prompt_password("🔑 CA key passphrase: ")
+                    .context("Failed to read passphrase")?;
+                if passphrase.is_empty() {
+                    anyhow::bail!("CA key passphrase must not be empty");
+                }
+                if confirm {
+                    let again = rpassword::prompt_password("   Confirm passphrase: ")
+                        .context("Failed to read passphrase")?;
+                    if again != passphrase {
+                        anyhow::bail!("Passphrases do not match");
+                    }
+                }
+                passphrase
+            }
+        };
+
+        Ok(self.ca_passphrase.get_or_init(|| passphrase))
+    }
+
+    fn build_name_constraints(&self) -> Result<NameConstraints> {
+        let mut permitted_subtrees: Vec<GeneralSubtree> = self
+            .name_constraints
+            .iter()
+            .map(|suffix| GeneralSubtree::DnsName(suffix.clone()))
+            .collect();
+        for range in PERMITTED_IP_RANGES {
+            let subnet = range
+                .parse::<CidrSubnet>()
+                .map_err(|_| anyhow::anyhow!("Invalid IP range: {}", range))?;
+            permitted_subtrees.push(GeneralSubtree::IpAddress(subnet));
+        }
+
+        Ok(NameConstraints {
+            permitted_subtrees,
+            excluded_subtrees: Vec::new(),
+        })
+    }
+
+    /// DNS suffixes the CA on disk is constrained to, or `None` if it is unconstrained.
+    fn ca_name_constraints(&self) -> Result<Option<Vec<String>>> {
+        let ca_cert_pem = fs::read_to_string(self.ca_cert_path())
+            .context("Failed to read CA certificate")?;
+        let ca_params = CertificateParams::from_ca_cert_pem(&ca_cert_pem)
+            .context("Failed to parse CA certificate")?;
+
+        // Without a DNS subtree, DNS names are unconstrained (RFC 5280 section 4.2.1.10)
+        let suffixes: Vec<String> = ca_params
+            .name_constraints
+            .map(|nc| nc.permitted_subtrees)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|subtree| match subtree {
+                GeneralSubtree::DnsName(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+
+        Ok(if suffixes.is_empty() { None } else { Some(suffixes) })
+    }
+
+    /// Whether the CA on disk is allowed to sign a certificate for `domain`.
+    pub fn ca_permits(&self, domain: &str) -> Result<bool> {
+        if domain.parse::<IpAddr>().is_ok() {
+            return Ok(true); // only DNS suffixes are checked
+        }
+        let suffixes = match self.ca_name_constraints()? {
+            Some(s) => s,
+            None => return Ok(true),
+        };
+        let domain = domain.to_ascii_lowercase();
+        Ok(suffixes.iter().any(|suffix| {
+            let suffix = suffix.trim_start_matches('.').to_ascii_lowercase();
+            domain == suffix || domain.ends_with(&format!(".{}", suffix))
+        }))
+    }
+
+    /// Whether the CA on disk has name constraints and they permit `domain`. Unlike
+    /// `ca_permits`, an unconstrained CA (such as an imported mkcert one) does not count.
+    pub fn ca_constraints_permit(&self, domain: &str) -> Result<bool> {
+        Ok(self.ca_name_constraints()?.is_some() && self.ca_permits(domain)?)
+    }
+
     pub fn combined_cert_path(&self) -> PathBuf {
         self.cert_dir.join("server.crt")
     }
@@ -194,11 +620,73 @@ impl CertManager {
     }
 
     pub fn ca_cert_path(&self) -> PathBuf {
-        self.cert_dir.join("ca.crt")
+        match &self.external_ca {
+            Some((cert_path, _)) => cert_path.clone(),
+            None => self.ca_dir.join("ca.crt"),
+        }
     }
 
     fn ca_key_path(&self) -> PathBuf {
-        self.cert_dir.join("ca.key")
+        match &self.external_ca {
+            Some((_, key_path)) => key_path.clone(),
+            None => self.ca_dir.join("ca.key"),
+        }
+    }
+
+    /// Write the system CA bundle plus the DevRelay CA (and the previous CA during a rotation)
+    /// to a single PEM file, for runtimes that read one bundle instead of the system store.
+    pub fn write_trust_bundle(&self, system_bundle: Option<&Path>) -> Result<PathBuf> {
+        let mut bundle = match system_bundle {
+            Some(path) => fs::read_to_string(path)
+                .with_context(|| format!("Failed to read system CA bundle: {}", path.display()))?,
+            None => String::new(),
+        };
+        for ca_path in [self.ca_cert_path(), self.old_ca_cert_path()] {
+            if !ca_path.exists() {
+                continue;
+            }
+            if !bundle.is_empty() && !bundle.ends_with('\n') {
+                bundle.push('\n');
+            }
+            bundle.push_str(&fs::read_to_string(&ca_path).context("Failed to read CA certificate")?);
+        }
+
+        create_private_dir(&self.ca_dir)?;
+        let path = self.trust_bundle_path();
+        fs::write(&path, bundle).context("Failed to write CA bundle")?;
+        Ok(path)
+    }
+
+    pub fn trust_bundle_path(&self) -> PathBuf {
+        self.ca_dir.join("ca-bundle.pem")
+    }
+
+    /// The previous CA certificate during a rotation.
+    pub fn old_ca_cert_path(&self) -> PathBuf {
+        self.ca_dir.join("ca.old.crt")
+    }
+
+    fn old_ca_key_path(&self) -> PathBuf {
+        self.ca_dir.join("ca.old.key")
+    }
+
+    /// Certificate files in cert_dir (per-host, combined and other `.crt` files), excluding the CA.
+    pub fn cert_files(&self) -> Result<Vec<PathBuf>> {
+        if !self.cert_dir.exists() {
+            return Ok(Vec::new());
+        }
+        let ca_paths = [self.ca_cert_path(), self.old_ca_cert_path()];
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.cert_dir)
+            .with_context(|| format!("Failed to read cert directory: {}", self.cert_dir.display()))?
+        {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "crt") && !ca_paths.contains(&path) {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(files)
     }
 
     pub fn server_cert_path(&self, domain: &str) -> PathBuf {
@@ -209,3 +697,80 @@ impl CertManager {
         self.cert_dir.join(format!("{}.key", domain))
     }
 }
+
+/// SAN entry for a name: an IP address SAN for IPv4/IPv6 literals, otherwise a DNS name
+/// (wildcards such as `*.example.test` included).
+pub fn subject_alt_name(name: &str) -> Result<SanType> {
+    if let Ok(ip) = name.parse::<IpAddr>() {
+        return Ok(SanType::IpAddress(ip));
+    }
+    let dns_name = Ia5String::try_from(name.to_string())
+        .map_err(|_| anyhow::anyhow!("Invalid domain name: {}", name))?;
+    Ok(SanType::DnsName(dns_name))
+}
+
+/// Create a directory that only the current user can access (mode 0700 on Unix).
+pub fn create_private_dir(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create CA directory: {}", dir.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+            .with_context(|| format!("Failed to set permissions on {}", dir.display()))?;
+    }
+    Ok(())
+}
+
+/// Write a private key that only the current user can read (mode 0600 on Unix).
+pub fn write_private_key(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?;
+        // mode() only applies to newly created files
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        file.write_all(contents.as_ref())?;
+    }
+    #[cfg(not(unix))]
+    fs::write(path, contents)?;
+
+    Ok(())
+}
+
+/// Check that a private key is not readable by group or other users: refuse to use it when
+/// `refuse` is set (keys DevRelay manages), otherwise only warn.
+fn check_key_permissions(path: &Path, refuse: bool) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = fs::metadata(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+            .permissions()
+            .mode();
+        if mode & 0o077 != 0 {
+            let message = format!(
+                "Private key {} is readable by other users (mode {:o}). Restrict it with:\n    chmod 600 {}",
+                path.display(),
+                mode & 0o777,
+                path.display()
+            );
+            if refuse {
+                anyhow::bail!(message);
+            }
+            println!("⚠️  {}", message);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (path, refuse);
+
+    Ok(())
+}
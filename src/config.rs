@@ -1,11 +1,197 @@
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
 use anyhow::{Context, Result};
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct Config {
     pub routes: Vec<Route>,
     pub tls: TlsConfig,
+    /// Per-port TLS policy for HTTPS listeners
+    #[serde(default)]
+    pub listeners: Vec<ListenerConfig>,
+    /// Local ACME server issuing certificates from the DevRelay CA
+    #[serde(default)]
+    pub acme: Option<AcmeConfig>,
+    /// Address families to listen on and write hosts entries for (default: ipv4 and ipv6)
+    #[serde(default)]
+    pub address_families: Vec<AddressFamily>,
+    /// Built-in DNS server resolving route hosts, instead of /etc/hosts entries
+    #[serde(default)]
+    pub dns: Option<DnsConfig>,
+    /// Address to listen on and point hosts entries to (e.g. 127.0.0.2), so several projects
+    /// can each use the same port; 127.0.0.1 and ::1 when not set
+    #[serde(default)]
+    pub bind_address: Option<IpAddr>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6,
+}
+
+impl AddressFamily {
+    /// Loopback address listeners bind to and hosts entries point to
+    pub fn loopback(self) -> IpAddr {
+        match self {
+            AddressFamily::Ipv4 => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            AddressFamily::Ipv6 => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AcmeConfig {
+    /// Host name the ACME directory is served on (https://<host>/directory)
+    pub host: String,
+    /// HTTPS port for the directory (default: 443)
+    #[serde(default = "default_acme_port")]
+    pub port: u16,
+    /// Approve orders for configured route hosts without a challenge; other names use http-01
+    #[serde(default = "default_true")]
+    pub auto_approve: bool,
+    /// Validity of issued certificates in days
+    #[serde(default = "default_acme_cert_days")]
+    pub cert_days: u32,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DnsConfig {
+    /// Loopback address to listen on (UDP)
+    #[serde(default = "default_dns_address")]
+    pub address: IpAddr,
+    #[serde(default = "default_dns_port")]
+    pub port: u16,
+    /// Resolver other names are forwarded to, e.g. "1.1.1.1" or "192.168.1.1:53"; without one
+    /// they are refused
+    #[serde(default)]
+    pub upstream: Option<String>,
+    /// Domains answered authoritatively (unknown names get NXDOMAIN unless there is an
+    /// upstream). When empty, the reserved top-level domains of the route hosts are used.
+    #[serde(default)]
+    pub domains: Vec<String>,
+    /// On Linux, make systemd-resolved send queries for `domains` to this server. Requires
+    /// `domains` to be set.
+    #[serde(default)]
+    pub systemd_resolved: bool,
+}
+
+fn default_dns_address() -> IpAddr {
+    IpAddr::V4(Ipv4Addr::LOCALHOST)
+}
+
+fn default_dns_port() -> u16 {
+    5354
+}
+
+impl DnsConfig {
+    pub fn listen_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.address, self.port)
+    }
+
+    /// The upstream resolver; port 53 unless given.
+    pub fn upstream_addr(&self) -> Result<Option<SocketAddr>> {
+        let Some(upstream) = &self.upstream else {
+            return Ok(None);
+        };
+        let addr = upstream
+            .parse::<SocketAddr>()
+            .or_else(|_| upstream.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 53)))
+            .with_context(|| format!("Invalid dns.upstream: {}", upstream))?;
+        Ok(Some(addr))
+    }
+
+    /// The explicitly configured `domains`, for routing queries to this server. Refuses an empty
+    /// list and public top-level domains, which would send e.g. all of `.com` here.
+    pub fn split_dns_domains(&self) -> Result<Vec<String>> {
+        anyhow::ensure!(
+            !self.domains.is_empty(),
+            "dns.systemd_resolved needs dns.domains, the domains to send to the DNS server (e.g. [\"test\"])"
+        );
+        let mut domains = Vec::new();
+        for domain in &self.domains {
+            let domain = domain.trim_matches('.').to_ascii_lowercase();
+            if !domain.contains('.') && !is_reserved_domain(&domain) {
+                anyhow::bail!(
+                    "Refusing to route the public top-level domain {:?} to the DNS server; use a \
+                     reserved one such as \"test\", or a domain you own",
+                    domain
+                );
+            }
+            domains.push(domain);
+        }
+        domains.sort();
+        domains.dedup();
+        Ok(domains)
+    }
+}
+
+/// Top-level domains reserved for local use, which never resolve publicly (RFC 2606, 6761, 6762,
+/// 8375 and ICANN's .internal).
+const RESERVED_TLDS: &[&str] = &["test", "example", "invalid", "localhost", "local", "internal"];
+
+/// Whether `name` is under a reserved top-level domain (or home.arpa), so claiming it cannot
+/// shadow public names. Case-insensitive.
+pub fn is_reserved_domain(name: &str) -> bool {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    let tld = name.rsplit('.').next().unwrap_or_default();
+    RESERVED_TLDS.contains(&tld) || name == "home.arpa" || name.ends_with(".home.arpa")
+}
+
+/// Whether `host` is `pattern`, or is covered by a `*.suffix` wildcard pattern (one label, as
+/// for certificates). Case-insensitive.
+pub fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => host
+            .split_once('.')
+            .is_some_and(|(label, rest)| !label.is_empty() && rest.eq_ignore_ascii_case(suffix)),
+        None => pattern.eq_ignore_ascii_case(host),
+    }
+}
+
+fn default_acme_port() -> u16 {
+    443
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_acme_cert_days() -> u32 {
+    30
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct ListenerConfig {
+    pub port: u16,
+    /// Lowest TLS version accepted (default: tls1.2)
+    #[serde(default)]
+    pub min_version: Option<TlsVersion>,
+    /// Highest TLS version accepted (default: tls1.3)
+    #[serde(default)]
+    pub max_version: Option<TlsVersion>,
+    /// OpenSSL cipher list for TLS 1.2 and below, e.g. "ECDHE-ECDSA-AES128-GCM-SHA256"
+    #[serde(default)]
+    pub ciphers: Option<String>,
+    /// TLS 1.3 cipher suites, e.g. "TLS_AES_256_GCM_SHA384:TLS_CHACHA20_POLY1305_SHA256"
+    #[serde(default)]
+    pub ciphersuites: Option<String>,
+    /// Key exchange groups in preference order, e.g. ["X25519", "P-256"]
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// Protocols offered via ALPN: "h2" and/or "http/1.1" (default: http/1.1 only)
+    #[serde(default)]
+    pub alpn: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum TlsVersion {
+    #[serde(rename = "tls1.2")]
+    Tls12,
+    #[serde(rename = "tls1.3")]
+    Tls13,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -18,6 +204,45 @@ pub struct Route {
     pub backend_port: u16,
     #[serde(default)]
     pub backend_tls: bool,
+    /// Request client certificates chained to the DevRelay CA (mTLS): off, optional or required
+    #[serde(default)]
+    pub client_auth: ClientAuth,
+    /// Verification and client certificate settings for the backend connection (with `backend_tls`)
+    #[serde(default)]
+    pub backend_tls_options: Option<BackendTlsOptions>,
+    /// Address to listen on for this route, overriding the top-level `bind_address`
+    #[serde(default)]
+    pub bind_address: Option<IpAddr>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct BackendTlsOptions {
+    /// CA certificate (PEM, may hold several) to verify the backend with instead of the system roots
+    #[serde(default)]
+    pub ca_cert: Option<String>,
+    /// Accept any backend certificate (no chain or hostname verification)
+    #[serde(default)]
+    pub skip_verify: bool,
+    /// SNI sent to the backend and the name its certificate is verified against (default: `backend`)
+    #[serde(default)]
+    pub sni: Option<String>,
+    /// Client certificate (PEM, leaf first) presented to backends that require mTLS
+    #[serde(default)]
+    pub client_cert: Option<String>,
+    /// Private key for `client_cert`
+    #[serde(default)]
+    pub client_key: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientAuth {
+    #[default]
+    Off,
+    /// Verify a client certificate if one is presented
+    Optional,
+    /// Reject requests without a valid client certificate
+    Required,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -25,6 +250,110 @@ pub struct TlsConfig {
     pub enabled: bool,
     pub cert_dir: String,
     pub ca_name: String,
+    /// Directory holding the CA cert and key. Defaults to a per-user directory
+    /// ($XDG_DATA_HOME/devrelay) so every project shares one trusted CA.
+    #[serde(default)]
+    pub ca_dir: Option<String>,
+    /// Existing CA certificate to sign with instead of generating one (e.g. mkcert's rootCA.pem).
+    /// Must be set together with `ca_key`; the CA is assumed to be trusted already.
+    #[serde(default)]
+    pub ca_cert: Option<String>,
+    /// Private key for `ca_cert` (e.g. mkcert's rootCA-key.pem)
+    #[serde(default)]
+    pub ca_key: Option<String>,
+    /// Additional names for the TLS listener certificate, e.g. "localhost", "127.0.0.1", "::1"
+    /// or a LAN IP such as "192.168.1.20" so the proxy also validates when reached by address
+    #[serde(default)]
+    pub extra_sans: Vec<String>,
+    /// Restrict the generated CA with X.509 name constraints (applied when the CA is created)
+    #[serde(default)]
+    pub name_constraints: Option<NameConstraintsConfig>,
+    /// Encrypt the CA key with a passphrase (from DEVRELAY_CA_PASSPHRASE or prompted at startup)
+    #[serde(default)]
+    pub encrypt_ca_key: bool,
+    /// Issue short-lived leaf certificates (e.g. "6h", "30m") and rotate them while running
+    #[serde(default)]
+    pub leaf_lifetime: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct NameConstraintsConfig {
+    /// DNS suffixes the CA may sign for (e.g. ".test", ".localhost").
+    /// When empty, the top-level domains of the configured route hosts are used.
+    #[serde(default)]
+    pub permitted: Vec<String>,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            cert_dir: "./certs".to_string(),
+            ca_name: "DevRelay CA".to_string(),
+            ca_dir: None,
+            ca_cert: None,
+            ca_key: None,
+            extra_sans: Vec::new(),
+            name_constraints: None,
+            encrypt_ca_key: false,
+            leaf_lifetime: None,
+        }
+    }
+}
+
+impl TlsConfig {
+    /// Parsed `leaf_lifetime`, if short-lived leaf certificates are enabled.
+    pub fn leaf_lifetime(&self) -> Result<Option<std::time::Duration>> {
+        let Some(value) = &self.leaf_lifetime else {
+            return Ok(None);
+        };
+        let lifetime = parse_duration(value)
+            .with_context(|| format!("Invalid tls.leaf_lifetime: {:?} (e.g. \"6h\" or \"30m\")", value))?;
+        if lifetime < std::time::Duration::from_secs(60) {
+            anyhow::bail!("tls.leaf_lifetime must be at least 1m");
+        }
+        // Longer than the one-year default is not short-lived, and would overflow date math
+        if lifetime > std::time::Duration::from_secs(365 * 24 * 60 * 60) {
+            anyhow::bail!("tls.leaf_lifetime must be at most 365d");
+        }
+        Ok(Some(lifetime))
+    }
+
+    /// Directory for the CA cert and key: `ca_dir` if set, otherwise the per-user data directory.
+    pub fn ca_dir(&self) -> PathBuf {
+        match &self.ca_dir {
+            Some(dir) => expand_home(dir),
+            None => default_ca_dir(),
+        }
+    }
+
+    /// Cert and key paths of an imported CA, if `ca_cert`/`ca_key` are configured.
+    pub fn external_ca(&self) -> Result<Option<(PathBuf, PathBuf)>> {
+        match (&self.ca_cert, &self.ca_key) {
+            (Some(cert), Some(key)) => Ok(Some((expand_home(cert), expand_home(key)))),
+            (None, None) => Ok(None),
+            _ => anyhow::bail!("tls.ca_cert and tls.ca_key must be set together"),
+        }
+    }
+}
+
+/// Per-user CA location following the XDG base directory spec ($XDG_DATA_HOME/devrelay,
+/// falling back to ~/.local/share/devrelay).
+fn default_ca_dir() -> PathBuf {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .unwrap_or_else(|| PathBuf::from("."));
+    data_home.join("devrelay")
+}
+
+/// Expand a leading `~/` to the user's home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
 }
 
 impl Config {
@@ -38,9 +367,341 @@ impl Config {
         Ok(config)
     }
 
+    /// DNS suffixes the CA should be constrained to, or empty if name constraints are disabled.
+    /// Suffixes are only derived from hosts under reserved top-level domains: deriving `.dev`
+    /// from myapp.dev would let the CA sign for every `.dev` site, so that is refused.
+    pub fn name_constraint_suffixes(&self) -> Result<Vec<String>> {
+        let nc = match &self.tls.name_constraints {
+            Some(nc) => nc,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut suffixes: Vec<String> = if nc.permitted.is_empty() {
+            let names: Vec<&str> = self
+                .routes
+                .iter()
+                .map(|r| r.host.as_str())
+                .chain(self.tls.extra_sans.iter().map(|s| s.as_str()))
+                .filter(|name| name.parse::<std::net::IpAddr>().is_err())
+                .collect();
+            if let Some(public) = names.iter().find(|name| !is_reserved_domain(name)) {
+                anyhow::bail!(
+                    "Refusing to derive name constraints from {:?}: it would permit its whole \
+                     public top-level domain. List the domains in tls.name_constraints.permitted \
+                     instead (e.g. [\"{}\"])",
+                    public,
+                    public.trim_start_matches("*.").trim_end_matches('.')
+                );
+            }
+            names
+                .iter()
+                .map(|name| name.trim_end_matches('.').to_ascii_lowercase())
+                .map(|name| {
+                    if name == "home.arpa" || name.ends_with(".home.arpa") {
+                        "home.arpa".to_string()
+                    } else {
+                        name.rsplit('.').next().unwrap_or_default().to_string()
+                    }
+                })
+                .collect()
+        } else {
+            nc.permitted
+                .iter()
+                .map(|s| s.trim_start_matches('.').to_string())
+                .collect()
+        };
+        for suffix in suffixes.iter_mut() {
+            suffix.make_ascii_lowercase();
+        }
+        suffixes.sort();
+        suffixes.dedup();
+        Ok(suffixes)
+    }
+
+    /// Every host name devrelay answers for: the route hosts plus the ACME directory host.
+    pub fn host_names(&self) -> Vec<String> {
+        let mut hosts: Vec<String> = self.routes.iter().map(|r| r.host.clone()).collect();
+        if let Some(acme) = &self.acme
+            && !hosts.contains(&acme.host)
+        {
+            hosts.push(acme.host.clone());
+        }
+        hosts
+    }
+
+    /// Domains the built-in DNS server is authoritative for: `dns.domains`, or the reserved
+    /// top-level domains of the host names. Public ones like `.dev` are left out, so the server
+    /// never claims names it cannot know.
+    pub fn dns_domains(&self) -> Vec<String> {
+        let configured = self.dns.as_ref().map(|d| d.domains.clone()).unwrap_or_default();
+        let mut domains: Vec<String> = if configured.is_empty() {
+            self.host_names()
+                .iter()
+                .filter(|name| name.parse::<IpAddr>().is_err() && is_reserved_domain(name))
+                .filter_map(|name| name.rsplit('.').next())
+                .map(|tld| tld.to_string())
+                .collect()
+        } else {
+            configured
+                .iter()
+                .map(|d| d.trim_matches('.').to_string())
+                .collect()
+        };
+        for domain in domains.iter_mut() {
+            domain.make_ascii_lowercase();
+        }
+        domains.sort();
+        domains.dedup();
+        domains
+    }
+
+    /// Host and port of everything served over TLS: `listen_tls` routes and the ACME directory.
+    pub fn tls_endpoints(&self) -> Vec<(String, u16)> {
+        if !self.tls.enabled {
+            return Vec::new();
+        }
+        // Wildcard hosts are checked with a name they cover
+        let mut endpoints: Vec<(String, u16)> = self
+            .routes
+            .iter()
+            .filter(|r| r.listen_tls)
+            .map(|r| (r.host.replace('*', "devrelay-check"), r.port))
+            .collect();
+        if let Some(acme) = &self.acme {
+            endpoints.push((acme.host.clone(), acme.port));
+        }
+        endpoints.dedup();
+        endpoints
+    }
+
+    /// Configured address families, IPv4 and IPv6 when none are set.
+    pub fn address_families(&self) -> Vec<AddressFamily> {
+        let mut families = self.address_families.clone();
+        if families.is_empty() {
+            families = vec![AddressFamily::Ipv4, AddressFamily::Ipv6];
+        }
+        let mut seen = Vec::new();
+        families.retain(|family| {
+            let first = !seen.contains(family);
+            seen.push(*family);
+            first
+        });
+        families
+    }
+
+    /// Loopback addresses route hosts resolve to, one per address family.
+    pub fn loopback_addresses(&self) -> Vec<IpAddr> {
+        self.address_families()
+            .into_iter()
+            .map(AddressFamily::loopback)
+            .collect()
+    }
+
+    /// Address the route's port is bound to, or `None` for the loopback addresses (and the LAN
+    /// address in `--lan` mode).
+    pub fn route_bind_address(&self, route: &Route) -> Option<IpAddr> {
+        route.bind_address.or(self.bind_address)
+    }
+
+    /// Bind address of the listener serving `host` (see `route_bind_address`).
+    fn host_bind_address(&self, host: &str) -> Option<IpAddr> {
+        match self.get_route_by_host(host) {
+            Some(route) => self.route_bind_address(route),
+            None => self.bind_address,
+        }
+    }
+
+    /// Addresses `host` is served on: its bind address, or the loopback addresses.
+    pub fn addresses_for_host(&self, host: &str) -> Vec<IpAddr> {
+        match self.host_bind_address(host) {
+            Some(ip) => vec![ip],
+            None => self.loopback_addresses(),
+        }
+    }
+
+    /// `.local` host names to announce over multicast DNS in `--lan` mode: those served on the
+    /// LAN address, so not wildcards or hosts with a bind address of their own.
+    pub fn mdns_hosts(&self) -> Vec<String> {
+        let mut hosts: Vec<String> = self
+            .host_names()
+            .into_iter()
+            .filter(|host| !host.starts_with("*.") && self.host_bind_address(host).is_none())
+            .map(|host| host.trim_end_matches('.').to_ascii_lowercase())
+            .filter(|host| host.ends_with(".local"))
+            .collect();
+        hosts.sort();
+        hosts.dedup();
+        hosts
+    }
+
+    /// Route requests to the LAN address on `port` go to, as they carry no route host: the
+    /// first route on the port without a bind address of its own.
+    pub fn lan_route(&self, port: u16) -> Option<&Route> {
+        self.routes
+            .iter()
+            .find(|r| r.port == port && self.route_bind_address(r).is_none())
+    }
+
+    pub fn get_listener(&self, port: u16) -> Option<&ListenerConfig> {
+        self.listeners.iter().find(|l| l.port == port)
+    }
+
     pub fn get_route_by_host(&self, host: &str) -> Option<&Route> {
         // Strip port from host if present (e.g., "myapp.dev:8080" -> "myapp.dev")
         let host_without_port = host.split(':').next().unwrap_or(host);
-        self.routes.iter().find(|r| r.host == host_without_port)
+        // Exact hosts win over wildcards
+        self.routes
+            .iter()
+            .find(|r| r.host == host_without_port)
+            .or_else(|| {
+                self.routes
+                    .iter()
+                    .find(|r| r.host.starts_with("*.") && host_matches(&r.host, host_without_port))
+            })
+    }
+}
+
+/// Parse a duration such as "90s", "30m", "6h" or "2d".
+fn parse_duration(value: &str) -> Result<std::time::Duration> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .context("Missing unit (s, m, h or d)")?;
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount.parse().context("Missing amount")?;
+    anyhow::ensure!(amount > 0, "Duration must be greater than zero");
+    let unit_seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => anyhow::bail!("Unknown unit {:?} (use s, m, h or d)", unit),
+    };
+    let seconds = amount.checked_mul(unit_seconds).context("Duration is too large")?;
+    Ok(std::time::Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCALHOST_V4: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    const LOCALHOST_V6: IpAddr = IpAddr::V6(Ipv6Addr::LOCALHOST);
+
+    fn route(host: &str, port: u16, bind_address: Option<IpAddr>) -> Route {
+        Route {
+            host: host.to_string(),
+            port,
+            listen_tls: true,
+            backend: "localhost".to_string(),
+            backend_port: 3000,
+            backend_tls: false,
+            client_auth: ClientAuth::Off,
+            backend_tls_options: None,
+            bind_address,
+        }
+    }
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn name_constraints_are_derived_from_reserved_domains_only() {
+        let mut config = Config {
+            routes: vec![route("App.Test", 443, None), route("*.apps.localhost", 443, None)],
+            ..Config::default()
+        };
+        config.tls.extra_sans = vec!["localhost".into(), "127.0.0.1".into(), "nas.home.arpa".into()];
+        assert!(config.name_constraint_suffixes().unwrap().is_empty());
+
+        config.tls.name_constraints = Some(NameConstraintsConfig::default());
+        assert_eq!(
+            config.name_constraint_suffixes().unwrap(),
+            vec!["home.arpa", "localhost", "test"]
+        );
+
+        config.routes.push(route("myapp.dev", 443, None));
+        let err = config.name_constraint_suffixes().unwrap_err().to_string();
+        assert!(err.contains("\"myapp.dev\""), "{}", err);
+
+        // Listed suffixes are taken as given
+        config.tls.name_constraints = Some(NameConstraintsConfig {
+            permitted: vec![".test".into(), "myapp.dev".into()],
+        });
+        assert_eq!(config.name_constraint_suffixes().unwrap(), vec!["myapp.dev", "test"]);
+    }
+
+    #[test]
+    fn parses_durations() {
+        use std::time::Duration;
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration(" 30m ").unwrap(), Duration::from_secs(30 * 60));
+        assert_eq!(parse_duration("6h").unwrap(), Duration::from_secs(6 * 60 * 60));
+        assert_eq!(parse_duration("2d").unwrap(), Duration::from_secs(2 * 24 * 60 * 60));
+        for invalid in ["", "6", "h", "6w", "0s", "0d", "-1h", "213503982334602d"] {
+            assert!(parse_duration(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn leaf_lifetime_is_bounded() {
+        let mut tls = TlsConfig::default();
+        assert_eq!(tls.leaf_lifetime().unwrap(), None);
+        tls.leaf_lifetime = Some("6h".into());
+        assert_eq!(tls.leaf_lifetime().unwrap(), Some(std::time::Duration::from_secs(6 * 60 * 60)));
+        for invalid in ["30s", "366d", "99999999999999d"] {
+            tls.leaf_lifetime = Some(invalid.into());
+            assert!(tls.leaf_lifetime().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn route_bind_address_prefers_the_route() {
+        let bound = route("api.test", 443, Some(ip("127.0.0.3")));
+        let unbound = route("web.test", 443, None);
+        let mut config = Config::default();
+        assert_eq!(config.route_bind_address(&bound), Some(ip("127.0.0.3")));
+        assert_eq!(config.route_bind_address(&unbound), None);
+
+        config.bind_address = Some(ip("127.0.0.2"));
+        assert_eq!(config.route_bind_address(&bound), Some(ip("127.0.0.3")));
+        assert_eq!(config.route_bind_address(&unbound), Some(ip("127.0.0.2")));
+    }
+
+    #[test]
+    fn addresses_for_host_falls_back_to_loopback() {
+        let mut config = Config {
+            routes: vec![
+                route("api.test", 443, Some(ip("127.0.0.3"))),
+                route("web.test", 443, None),
+                route("*.apps.test", 443, Some(ip("127.0.0.4"))),
+            ],
+            ..Config::default()
+        };
+        assert_eq!(config.addresses_for_host("api.test"), vec![ip("127.0.0.3")]);
+        assert_eq!(config.addresses_for_host("web.test"), vec![LOCALHOST_V4, LOCALHOST_V6]);
+        assert_eq!(config.addresses_for_host("x.apps.test"), vec![ip("127.0.0.4")]);
+        // Hosts without a route, such as the ACME directory, use the top-level bind address
+        assert_eq!(config.addresses_for_host("acme.test"), vec![LOCALHOST_V4, LOCALHOST_V6]);
+
+        config.address_families = vec![AddressFamily::Ipv4];
+        assert_eq!(config.addresses_for_host("web.test"), vec![LOCALHOST_V4]);
+
+        config.bind_address = Some(ip("127.0.0.2"));
+        assert_eq!(config.addresses_for_host("web.test"), vec![ip("127.0.0.2")]);
+        assert_eq!(config.addresses_for_host("acme.test"), vec![ip("127.0.0.2")]);
+        assert_eq!(config.addresses_for_host("api.test"), vec![ip("127.0.0.3")]);
+    }
+
+    #[test]
+    fn address_families_removes_duplicates_in_any_order() {
+        use AddressFamily::{Ipv4, Ipv6};
+        let config = Config {
+            address_families: vec![Ipv6, Ipv4, Ipv6, Ipv4],
+            ..Config::default()
+        };
+        assert_eq!(config.address_families(), vec![Ipv6, Ipv4]);
+        assert_eq!(Config::default().address_families(), vec![Ipv4, Ipv6]);
     }
 }
@@ -0,0 +1,767 @@
+use crate::config::host_matches;
+use crate::install;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use pingora_core::server::ShutdownWatch;
+use pingora_core::services::background::BackgroundService;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_AAAA: u16 = 28;
+pub const TYPE_ANY: u16 = 255;
+pub const CLASS_IN: u16 = 1;
+pub const CLASS_ANY: u16 = 255;
+
+const RCODE_FORMERR: u8 = 1;
+const RCODE_NXDOMAIN: u8 = 3;
+const RCODE_NOTIMP: u8 = 4;
+const RCODE_REFUSED: u8 = 5;
+
+/// Short, so route changes take effect quickly
+const TTL: u32 = 10;
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(3);
+/// Largest UDP message we read; queries are far smaller
+const MAX_PACKET: usize = 4096;
+
+const RESOLVED_DROP_IN_DIR: &str = "/etc/systemd/resolved.conf.d";
+
+/// A question from a DNS message.
+pub struct Question {
+    /// Lowercase, without the trailing dot
+    pub name: String,
+    pub qtype: u16,
+    pub qclass: u16,
+}
+
+/// A resource record, as far as address records need it.
+pub struct Record {
+    /// Lowercase, without the trailing dot
+    pub name: String,
+    pub rtype: u16,
+    pub class: u16,
+    pub rdata: Vec<u8>,
+}
+
+/// The resource record sections of a DNS message.
+pub struct Records {
+    pub answers: Vec<Record>,
+    pub authority: Vec<Record>,
+    pub additional: Vec<Record>,
+}
+
+/// The header fields and questions of a DNS message (RFC 1035 section 4.1).
+pub struct Message {
+    pub id: u16,
+    pub flags: u16,
+    pub questions: Vec<Question>,
+    /// End of the question section in the packet
+    pub questions_end: usize,
+}
+
+impl Message {
+    pub fn is_response(&self) -> bool {
+        self.flags & 0x8000 != 0
+    }
+
+    pub fn opcode(&self) -> u16 {
+        (self.flags >> 11) & 0xF
+    }
+
+    /// Parse the header and question section; the other sections are ignored.
+    pub fn parse(packet: &[u8]) -> Option<Self> {
+        let id = read_u16(packet, 0)?;
+        let flags = read_u16(packet, 2)?;
+        let qdcount = read_u16(packet, 4)?;
+        let mut pos = 12;
+        let mut questions = Vec::new();
+        for _ in 0..qdcount {
+            let (name, end) = read_name(packet, pos)?;
+            questions.push(Question {
+                name,
+                qtype: read_u16(packet, end)?,
+                qclass: read_u16(packet, end + 2)?,
+            });
+            pos = end + 4;
+        }
+        Some(Self {
+            id,
+            flags,
+            questions,
+            questions_end: pos,
+        })
+    }
+
+    /// Parse the sections after the questions of `packet`, the message this was parsed from.
+    pub fn records(&self, packet: &[u8]) -> Option<Records> {
+        let mut pos = self.questions_end;
+        // Reads the section whose record count is at `count_pos` in the header
+        let mut section = |count_pos: usize| {
+            let mut records = Vec::new();
+            for _ in 0..read_u16(packet, count_pos)? {
+                let (name, end) = read_name(packet, pos)?;
+                let rdlength = read_u16(packet, end + 8)? as usize;
+                records.push(Record {
+                    name,
+                    rtype: read_u16(packet, end)?,
+                    class: read_u16(packet, end + 2)?,
+                    rdata: packet.get(end + 10..end + 10 + rdlength)?.to_vec(),
+                });
+                pos = end + 10 + rdlength;
+            }
+            Some(records)
+        };
+        Some(Records {
+            answers: section(6)?,
+            authority: section(8)?,
+            additional: section(10)?,
+        })
+    }
+}
+
+fn read_u16(packet: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([
+        *packet.get(pos)?,
+        *packet.get(pos + 1)?,
+    ]))
+}
+
+/// Read a possibly compressed name at `pos`; returns it and the position after it.
+fn read_name(packet: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut end = None;
+    // Bounds the number of compression pointers followed, so loops cannot hang us
+    for _ in 0..128 {
+        let len = *packet.get(pos)? as usize;
+        match len {
+            0 => {
+                let name = labels.join(".").to_ascii_lowercase();
+                return Some((name, end.unwrap_or(pos + 1)));
+            }
+            l if l & 0xC0 == 0xC0 => {
+                let target = (read_u16(packet, pos)? & 0x3FFF) as usize;
+                end.get_or_insert(pos + 2);
+                pos = target;
+            }
+            l if l < 64 => {
+                let label = packet.get(pos + 1..pos + 1 + l)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + l;
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Append `name` as uncompressed labels.
+pub fn write_name(out: &mut Vec<u8>, name: &str) {
+    for label in name.split('.').filter(|l| !l.is_empty()) {
+        out.push(label.len() as u8);
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+}
+
+/// Append a resource record for `ip` (A or AAAA) whose owner name is written by `name`.
+pub fn write_address_record(
+    out: &mut Vec<u8>,
+    name: impl FnOnce(&mut Vec<u8>),
+    ip: IpAddr,
+    class: u16,
+    ttl: u32,
+) {
+    name(out);
+    let (rtype, rdata) = match ip {
+        IpAddr::V4(v4) => (TYPE_A, v4.octets().to_vec()),
+        IpAddr::V6(v6) => (TYPE_AAAA, v6.octets().to_vec()),
+    };
+    out.extend_from_slice(&rtype.to_be_bytes());
+    out.extend_from_slice(&class.to_be_bytes());
+    out.extend_from_slice(&ttl.to_be_bytes());
+    out.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    out.extend_from_slice(&rdata);
+}
+
+/// Addresses of `ips` that answer a query of type `qtype`.
+pub fn addresses_for(ips: &[IpAddr], qtype: u16) -> Vec<IpAddr> {
+    ips.iter()
+        .copied()
+        .filter(|ip| match qtype {
+            TYPE_A => ip.is_ipv4(),
+            TYPE_AAAA => ip.is_ipv6(),
+            TYPE_ANY => true,
+            _ => false,
+        })
+        .collect()
+}
+
+/// The names the server answers for and the domains it is authoritative for.
+pub struct Zone {
+    /// Route hosts, `*.suffix` wildcards included, and the addresses they resolve to
+    hosts: Vec<(String, Vec<IpAddr>)>,
+    domains: Vec<String>,
+}
+
+impl Zone {
+    pub fn new(hosts: Vec<(String, Vec<IpAddr>)>, domains: Vec<String>) -> Self {
+        Self { hosts, domains }
+    }
+
+    /// Addresses of `name`, with exact hosts winning over wildcards.
+    fn resolve(&self, name: &str) -> Option<&[IpAddr]> {
+        self.hosts
+            .iter()
+            .find(|(host, _)| !host.starts_with("*.") && host_matches(host, name))
+            .or_else(|| self.hosts.iter().find(|(host, _)| host_matches(host, name)))
+            .map(|(_, addresses)| addresses.as_slice())
+    }
+
+    fn is_authoritative(&self, name: &str) -> bool {
+        self.domains.iter().any(|d| {
+            name == d
+                || name
+                    .strip_suffix(d.as_str())
+                    .is_some_and(|p| p.ends_with('.'))
+        })
+    }
+}
+
+enum Reply {
+    Respond(Vec<u8>),
+    Forward,
+    Ignore,
+}
+
+/// DNS server answering A/AAAA queries for route hosts with their listen addresses. Other names
+/// are forwarded upstream; without one, names under the zone's domains get NXDOMAIN and the rest
+/// are refused.
+pub struct DnsServer {
+    socket: std::net::UdpSocket,
+    zone: Arc<Zone>,
+    upstream: Option<SocketAddr>,
+}
+
+impl DnsServer {
+    /// Bind the UDP socket now, so errors show up at startup rather than in the service.
+    pub fn bind(addr: SocketAddr, zone: Zone, upstream: Option<SocketAddr>) -> Result<Self> {
+        let socket = std::net::UdpSocket::bind(addr)
+            .with_context(|| format!("Cannot bind DNS server to {}", addr))?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            zone: Arc::new(zone),
+            upstream,
+        })
+    }
+
+    fn reply(&self, packet: &[u8]) -> Reply {
+        let Some(query) = Message::parse(packet) else {
+            return Reply::Ignore;
+        };
+        if query.is_response() {
+            return Reply::Ignore;
+        }
+        if query.opcode() != 0 {
+            return Reply::Respond(response(packet, &query, RCODE_NOTIMP, &[], false));
+        }
+        let [question] = query.questions.as_slice() else {
+            return Reply::Respond(response(packet, &query, RCODE_FORMERR, &[], false));
+        };
+
+        if let Some(addresses) = self.zone.resolve(&question.name) {
+            let answers = match question.qclass {
+                CLASS_IN | CLASS_ANY => addresses_for(addresses, question.qtype),
+                _ => Vec::new(),
+            };
+            Reply::Respond(response(packet, &query, 0, &answers, true))
+        } else if self.upstream.is_some() {
+            // Even within the zone: the name may exist on the network, e.g. another machine
+            Reply::Forward
+        } else if self.zone.is_authoritative(&question.name) {
+            Reply::Respond(response(packet, &query, RCODE_NXDOMAIN, &[], true))
+        } else {
+            Reply::Respond(response(packet, &query, RCODE_REFUSED, &[], false))
+        }
+    }
+}
+
+/// Response to `query` echoing its question section (as sent, so 0x20 case randomization
+/// survives) followed by an address record per entry of `answers`.
+pub fn response(
+    packet: &[u8],
+    query: &Message,
+    rcode: u8,
+    answers: &[IpAddr],
+    authoritative: bool,
+) -> Vec<u8> {
+    // QR, the query's opcode and RD, then AA and RCODE
+    let mut flags = 0x8000 | (query.flags & 0x7900);
+    if authoritative {
+        flags |= 0x0400;
+    }
+    flags |= rcode as u16;
+
+    let mut out = Vec::with_capacity(query.questions_end + answers.len() * 28);
+    out.extend_from_slice(&query.id.to_be_bytes());
+    out.extend_from_slice(&flags.to_be_bytes());
+    out.extend_from_slice(&(query.questions.len() as u16).to_be_bytes());
+    out.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+    out.extend_from_slice(&[0, 0, 0, 0]);
+    out.extend_from_slice(&packet[12..query.questions_end]);
+    for ip in answers {
+        // Pointer to the question name at offset 12
+        write_address_record(
+            &mut out,
+            |out| out.extend_from_slice(&[0xC0, 12]),
+            *ip,
+            CLASS_IN,
+            TTL,
+        );
+    }
+    out
+}
+
+/// Relay a query to the upstream resolver and its answer back to the client.
+async fn forward(
+    socket: Arc<UdpSocket>,
+    upstream: SocketAddr,
+    packet: Vec<u8>,
+    client: SocketAddr,
+) -> Result<()> {
+    let local: SocketAddr = if upstream.is_ipv4() {
+        "0.0.0.0:0".parse()?
+    } else {
+        "[::]:0".parse()?
+    };
+    let upstream_socket = UdpSocket::bind(local).await?;
+    upstream_socket.connect(upstream).await?;
+    upstream_socket.send(&packet).await?;
+    let mut buf = vec![0u8; MAX_PACKET];
+    let len = tokio::time::timeout(UPSTREAM_TIMEOUT, upstream_socket.recv(&mut buf))
+        .await
+        .context("Upstream resolver timed out")??;
+    socket.send_to(&buf[..len], client).await?;
+    Ok(())
+}
+
+#[async_trait]
+impl BackgroundService for DnsServer {
+    async fn start(&self, mut shutdown: ShutdownWatch) {
+        let socket = match self.socket.try_clone().map(UdpSocket::from_std) {
+            Ok(Ok(socket)) => Arc::new(socket),
+            Ok(Err(e)) | Err(e) => {
+                eprintln!("DNS server failed to start: {}", e);
+                return;
+            }
+        };
+        let mut buf = vec![0u8; MAX_PACKET];
+        loop {
+            let (len, client) = tokio::select! {
+                _ = shutdown.changed() => return,
+                received = socket.recv_from(&mut buf) => match received {
+                    Ok(received) => received,
+                    Err(e) => {
+                        eprintln!("DNS server receive failed: {}", e);
+                        continue;
+                    }
+                },
+            };
+            let packet = &buf[..len];
+            match self.reply(packet) {
+                Reply::Respond(response) => {
+                    let _ = socket.send_to(&response, client).await;
+                }
+                Reply::Forward => {
+                    let Some(upstream) = self.upstream else {
+                        continue;
+                    };
+                    let socket = socket.clone();
+                    let packet = packet.to_vec();
+                    tokio::spawn(async move {
+                        if let Err(e) = forward(socket, upstream, packet, client).await {
+                            eprintln!("DNS forward to {} failed: {:#}", upstream, e);
+                        }
+                    });
+                }
+                Reply::Ignore => {}
+            }
+        }
+    }
+}
+
+/// Drop-in for the DNS server on `addr`, named after it so that each project's server has its
+/// own, e.g. `devrelay-127.0.0.1-5354.conf`.
+fn resolved_drop_in_path(addr: SocketAddr) -> PathBuf {
+    let ip: String = addr
+        .ip()
+        .to_string()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    Path::new(RESOLVED_DROP_IN_DIR).join(format!("devrelay-{}-{}.conf", ip, addr.port()))
+}
+
+fn resolved_drop_in(addr: SocketAddr, domains: &[String]) -> String {
+    let routing: Vec<String> = domains.iter().map(|d| format!("~{}", d)).collect();
+    format!(
+        "# Managed by devrelay\n[Resolve]\nDNS={}\nDomains={}\n",
+        addr,
+        routing.join(" ")
+    )
+}
+
+/// Point systemd-resolved at the DNS server for `domains` (split DNS), through a drop-in in
+/// resolved.conf.d. Returns false if systemd-resolved is not running.
+pub fn register_systemd_resolved(addr: SocketAddr, domains: &[String]) -> Result<bool> {
+    let active = std::process::Command::new("systemctl")
+        .args(["is-active", "--quiet", "systemd-resolved"])
+        .status()
+        .is_ok_and(|status| status.success());
+    if !active {
+        println!(
+            "⚠️  systemd-resolved is not running; point your resolver at the DNS server yourself."
+        );
+        return Ok(false);
+    }
+
+    if let Some(domain) = domains.iter().find(|d| {
+        !d.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+    }) {
+        anyhow::bail!("Invalid DNS domain: {:?}", domain);
+    }
+
+    let path = resolved_drop_in_path(addr);
+    let contents = resolved_drop_in(addr, domains);
+    if std::fs::read_to_string(&path).is_ok_and(|current| current == contents) {
+        println!(
+            "✅ systemd-resolved already sends {} to {}",
+            domains.join(", "),
+            addr
+        );
+        return Ok(true);
+    }
+
+    println!("🌐 Registering the DNS server with systemd-resolved...");
+    println!("   Waiting for authentication...");
+    install::run_with_sudo(&format!(
+        "mkdir -p '{}' && printf '%s' '{}' > '{}' && systemctl restart systemd-resolved",
+        RESOLVED_DROP_IN_DIR,
+        contents,
+        path.display()
+    ))
+    .context("Failed to configure systemd-resolved")?;
+    println!(
+        "✅ systemd-resolved sends {} to {}",
+        domains.join(", "),
+        addr
+    );
+    Ok(true)
+}
+
+/// Remove the systemd-resolved drop-in of the DNS server on `addr`, if there is one.
+pub fn unregister_systemd_resolved(addr: SocketAddr) -> Result<()> {
+    let path = resolved_drop_in_path(addr);
+    if !path.exists() {
+        return Ok(());
+    }
+    println!("\n🌐 Removing the DNS server from systemd-resolved...");
+    println!("   Waiting for authentication...");
+    install::run_with_sudo(&format!(
+        "rm -f '{}' && systemctl restart systemd-resolved",
+        path.display()
+    ))
+    .context("Failed to configure systemd-resolved")?;
+    println!("✅ systemd-resolved configuration removed");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    const LOCALHOST_V4: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    /// A standard query (RD set) for `name`.
+    fn query(name: &str, qtype: u16, qclass: u16) -> Vec<u8> {
+        let mut packet = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        write_name(&mut packet, name);
+        packet.extend_from_slice(&qtype.to_be_bytes());
+        packet.extend_from_slice(&qclass.to_be_bytes());
+        packet
+    }
+
+    fn server(upstream: Option<SocketAddr>) -> DnsServer {
+        let zone = Zone::new(
+            vec![
+                (
+                    "app.test".to_string(),
+                    vec![LOCALHOST_V4, IpAddr::V6(Ipv6Addr::LOCALHOST)],
+                ),
+                ("*.apps.test".to_string(), vec![LOCALHOST_V4]),
+                ("myapp.dev".to_string(), vec![LOCALHOST_V4]),
+            ],
+            vec!["test".to_string()],
+        );
+        DnsServer::bind("127.0.0.1:0".parse().unwrap(), zone, upstream).unwrap()
+    }
+
+    /// RCODE, AA flag and answer count of a reply, or None if it is not answered here.
+    fn answered(reply: Reply) -> Option<(u8, bool, u16)> {
+        match reply {
+            Reply::Respond(packet) => {
+                let flags = read_u16(&packet, 2).unwrap();
+                Some((
+                    (flags & 0xF) as u8,
+                    flags & 0x0400 != 0,
+                    read_u16(&packet, 6).unwrap(),
+                ))
+            }
+            Reply::Forward | Reply::Ignore => None,
+        }
+    }
+
+    #[test]
+    fn parse_reads_header_and_questions() {
+        let packet = query("App.Test", TYPE_AAAA, CLASS_IN);
+        let message = Message::parse(&packet).unwrap();
+        assert_eq!(message.id, 0x1234);
+        assert!(!message.is_response());
+        assert_eq!(message.opcode(), 0);
+        assert_eq!(message.questions_end, packet.len());
+        let [question] = message.questions.as_slice() else {
+            panic!("expected one question");
+        };
+        assert_eq!(question.name, "app.test");
+        assert_eq!((question.qtype, question.qclass), (TYPE_AAAA, CLASS_IN));
+    }
+
+    #[test]
+    fn read_name_follows_compression_pointers() {
+        let mut packet = vec![0; 12];
+        write_name(&mut packet, "apps.test");
+        let second = packet.len();
+        packet.extend_from_slice(&[3, b'w', b'w', b'w', 0xC0, 12]);
+        packet.extend_from_slice(&[0xFF]);
+
+        assert_eq!(
+            read_name(&packet, 12),
+            Some(("apps.test".to_string(), second))
+        );
+        // The name ends after the first pointer, not where the pointer led
+        assert_eq!(
+            read_name(&packet, second),
+            Some(("www.apps.test".to_string(), second + 6))
+        );
+    }
+
+    #[test]
+    fn read_name_rejects_pointer_loops() {
+        let mut packet = vec![0; 12];
+        packet.extend_from_slice(&[0xC0, 12]);
+        assert_eq!(read_name(&packet, 12), None);
+
+        let mut packet = vec![0; 12];
+        packet.extend_from_slice(&[1, b'a', 0xC0, 16, 1, b'b', 0xC0, 12]);
+        assert_eq!(read_name(&packet, 12), None);
+    }
+
+    #[test]
+    fn parse_rejects_truncated_packets() {
+        let packet = query("app.test", TYPE_A, CLASS_IN);
+        for len in 0..packet.len() {
+            assert!(Message::parse(&packet[..len]).is_none(), "length {}", len);
+        }
+
+        // A second question that is not there
+        let mut packet = query("app.test", TYPE_A, CLASS_IN);
+        packet[5] = 2;
+        assert!(Message::parse(&packet).is_none());
+
+        // Label types 0x40 and 0x80 are reserved
+        let mut packet = query("app.test", TYPE_A, CLASS_IN);
+        packet[12] = 0x80;
+        assert!(Message::parse(&packet).is_none());
+
+        // A pointer past the end
+        let mut packet = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        packet.extend_from_slice(&[0xC0, 200, 0, 1, 0, 1]);
+        assert!(Message::parse(&packet).is_none());
+    }
+
+    #[test]
+    fn response_echoes_question_and_appends_answers() {
+        let packet = query("ApP.tEsT", TYPE_ANY, CLASS_IN);
+        let message = Message::parse(&packet).unwrap();
+        let v6 = IpAddr::V6(Ipv6Addr::LOCALHOST);
+        let out = response(&packet, &message, 0, &[LOCALHOST_V4, v6], true);
+
+        assert_eq!(&out[..2], &[0x12, 0x34]);
+        // QR, RD and AA
+        assert_eq!(read_u16(&out, 2), Some(0x8500));
+        assert_eq!(read_u16(&out, 4), Some(1));
+        assert_eq!(read_u16(&out, 6), Some(2));
+        assert_eq!(&out[8..12], &[0, 0, 0, 0]);
+        // The question keeps its case
+        assert_eq!(&out[12..packet.len()], &packet[12..]);
+
+        let answers = &out[packet.len()..];
+        let mut a = vec![0xC0, 12, 0, 1, 0, 1];
+        a.extend_from_slice(&TTL.to_be_bytes());
+        a.extend_from_slice(&[0, 4, 127, 0, 0, 1]);
+        let mut aaaa = vec![0xC0, 12, 0, 28, 0, 1];
+        aaaa.extend_from_slice(&TTL.to_be_bytes());
+        aaaa.extend_from_slice(&[0, 16]);
+        aaaa.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        assert_eq!(answers, [a, aaaa].concat().as_slice());
+
+        let refused = response(&packet, &message, RCODE_REFUSED, &[], false);
+        assert_eq!(read_u16(&refused, 2), Some(0x8105));
+        assert_eq!(refused.len(), packet.len());
+    }
+
+    #[test]
+    fn zone_prefers_exact_hosts_over_wildcards() {
+        let exact = vec![IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2))];
+        let wildcard = vec![LOCALHOST_V4];
+        let zone = Zone::new(
+            vec![
+                ("*.apps.test".to_string(), wildcard.clone()),
+                ("admin.apps.test".to_string(), exact.clone()),
+            ],
+            vec!["test".to_string()],
+        );
+        assert_eq!(zone.resolve("admin.apps.test"), Some(exact.as_slice()));
+        assert_eq!(
+            zone.resolve("feature-x.apps.test"),
+            Some(wildcard.as_slice())
+        );
+        // A wildcard covers exactly one label
+        assert_eq!(zone.resolve("apps.test"), None);
+        assert_eq!(zone.resolve("a.b.apps.test"), None);
+    }
+
+    #[test]
+    fn host_matches_is_case_insensitive_and_one_label_deep() {
+        assert!(host_matches("App.Test", "app.test"));
+        assert!(host_matches("*.apps.test", "X.APPS.test"));
+        assert!(!host_matches("*.apps.test", ".apps.test"));
+        assert!(!host_matches("*.apps.test", "apps.test"));
+        assert!(!host_matches("*.apps.test", "a.b.apps.test"));
+        assert!(!host_matches("app.test", "myapp.test"));
+    }
+
+    #[test]
+    fn zone_is_authoritative_for_domains_and_below() {
+        let zone = Zone::new(
+            Vec::new(),
+            vec!["test".to_string(), "corp.example".to_string()],
+        );
+        assert!(zone.is_authoritative("test"));
+        assert!(zone.is_authoritative("unknown.test"));
+        assert!(zone.is_authoritative("a.corp.example"));
+        assert!(!zone.is_authoritative("attest"));
+        assert!(!zone.is_authoritative("test.com"));
+        assert!(!zone.is_authoritative("example"));
+    }
+
+    #[test]
+    fn reply_without_upstream() {
+        let server = server(None);
+        let reply = |name: &str, qtype: u16| server.reply(&query(name, qtype, CLASS_IN));
+
+        assert_eq!(answered(reply("app.test", TYPE_A)), Some((0, true, 1)));
+        assert_eq!(answered(reply("app.test", TYPE_ANY)), Some((0, true, 2)));
+        assert_eq!(
+            answered(reply("x.apps.test", TYPE_AAAA)),
+            Some((0, true, 0))
+        );
+        assert_eq!(answered(reply("app.test", 16)), Some((0, true, 0)));
+        // Unknown names in the zone do not exist; others are not ours to answer
+        assert_eq!(
+            answered(reply("unknown.test", TYPE_A)),
+            Some((RCODE_NXDOMAIN, true, 0))
+        );
+        assert_eq!(
+            answered(reply("example.com", TYPE_A)),
+            Some((RCODE_REFUSED, false, 0))
+        );
+        // Hosts outside the zone's domains are still answered
+        assert_eq!(answered(reply("myapp.dev", TYPE_A)), Some((0, true, 1)));
+        assert_eq!(
+            answered(reply("other.dev", TYPE_A)),
+            Some((RCODE_REFUSED, false, 0))
+        );
+        // Other classes get no records
+        assert_eq!(
+            answered(server.reply(&query("app.test", TYPE_A, 3))),
+            Some((0, true, 0))
+        );
+    }
+
+    #[test]
+    fn reply_forwards_unknown_names_to_upstream() {
+        let server = server(Some("127.0.0.1:53".parse().unwrap()));
+        let reply = |name: &str| server.reply(&query(name, TYPE_A, CLASS_IN));
+
+        assert_eq!(answered(reply("app.test")), Some((0, true, 1)));
+        assert!(matches!(reply("unknown.test"), Reply::Forward));
+        assert!(matches!(reply("example.com"), Reply::Forward));
+    }
+
+    #[test]
+    fn reply_rejects_unusual_messages() {
+        let server = server(None);
+
+        let mut response = query("app.test", TYPE_A, CLASS_IN);
+        response[2] |= 0x80;
+        assert!(matches!(server.reply(&response), Reply::Ignore));
+        assert!(matches!(server.reply(&[0x12, 0x34, 0x01]), Reply::Ignore));
+
+        // Opcode 2 (STATUS)
+        let mut status = query("app.test", TYPE_A, CLASS_IN);
+        status[2] |= 0x10;
+        assert_eq!(
+            answered(server.reply(&status)),
+            Some((RCODE_NOTIMP, false, 0))
+        );
+
+        let mut two_questions = query("app.test", TYPE_A, CLASS_IN);
+        two_questions[5] = 2;
+        two_questions.extend_from_slice(&[0xC0, 12, 0, 1, 0, 1]);
+        assert_eq!(
+            answered(server.reply(&two_questions)),
+            Some((RCODE_FORMERR, false, 0))
+        );
+    }
+
+    #[test]
+    fn drop_in_is_named_after_the_server_address() {
+        assert_eq!(
+            resolved_drop_in_path("127.0.0.1:5354".parse().unwrap()),
+            Path::new("/etc/systemd/resolved.conf.d/devrelay-127.0.0.1-5354.conf")
+        );
+        assert_eq!(
+            resolved_drop_in_path("[::1]:53".parse().unwrap()),
+            Path::new("/etc/systemd/resolved.conf.d/devrelay-__1-53.conf")
+        );
+        assert_eq!(
+            resolved_drop_in(
+                "127.0.0.2:5354".parse().unwrap(),
+                &["test".to_string(), "internal".to_string()]
+            ),
+            "# Managed by devrelay\n[Resolve]\nDNS=127.0.0.2:5354\nDomains=~test ~internal\n"
+        );
+    }
+}
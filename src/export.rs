@@ -0,0 +1,262 @@
+use anyhow::{Context, Result};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkcs12::Pkcs12;
+use openssl::pkey::PKey;
+use openssl::sha::Sha1;
+use openssl::stack::Stack;
+use openssl::x509::X509;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Default password for Java truststores (the JDK's own cacerts uses the same one).
+pub const DEFAULT_JKS_PASSWORD: &str = "changeit";
+
+const JKS_MAGIC: u32 = 0xFEED_FEED;
+const JKS_VERSION: u32 = 2;
+const JKS_TRUSTED_CERT_TAG: u32 = 2;
+
+/// DER encoding of the first certificate in a PEM string (e.g. a `.cer` for Android/iOS).
+pub fn pem_to_der(cert_pem: &str) -> Result<Vec<u8>> {
+    let cert = X509::from_pem(cert_pem.as_bytes()).context("Failed to parse certificate")?;
+    cert.to_der().context("Failed to encode certificate as DER")
+}
+
+/// PKCS#8 DER encoding of a PEM private key.
+pub fn key_pem_to_der(key_pem: &str) -> Result<Vec<u8>> {
+    let key =
+        PKey::private_key_from_pem(key_pem.as_bytes()).context("Failed to parse private key")?;
+    key.private_key_to_pkcs8()
+        .context("Failed to encode private key as PKCS#8")
+}
+
+/// Password-protected PKCS#12 (`.p12`/`.pfx`) bundle of a certificate, its key and the CA chain.
+/// By default it uses OpenSSL's current algorithms (AES-256 with PBKDF2, SHA-256 MAC); `legacy`
+/// switches to 3DES and a SHA-1 MAC, which older JDKs (before 8u301), macOS Keychain and
+/// Windows before Server 2019 need.
+pub fn pkcs12(
+    friendly_name: &str,
+    cert_pem: &str,
+    key_pem: &str,
+    ca_pem: &str,
+    password: &str,
+    legacy: bool,
+) -> Result<Vec<u8>> {
+    let cert = X509::from_pem(cert_pem.as_bytes()).context("Failed to parse certificate")?;
+    let key =
+        PKey::private_key_from_pem(key_pem.as_bytes()).context("Failed to parse private key")?;
+
+    let mut chain = Stack::new()?;
+    for ca in X509::stack_from_pem(ca_pem.as_bytes()).context("Failed to parse CA certificate")? {
+        chain.push(ca)?;
+    }
+
+    let mut builder = Pkcs12::builder();
+    builder.name(friendly_name).pkey(&key).cert(&cert).ca(chain);
+    if legacy {
+        builder
+            .key_algorithm(Nid::PBE_WITHSHA1AND3_KEY_TRIPLEDES_CBC)
+            .cert_algorithm(Nid::PBE_WITHSHA1AND3_KEY_TRIPLEDES_CBC)
+            .mac_md(MessageDigest::sha1());
+    }
+    let pkcs12 = builder
+        .build2(password)
+        .context("Failed to build PKCS#12 bundle")?;
+    pkcs12.to_der().context("Failed to encode PKCS#12 bundle")
+}
+
+/// Java KeyStore (JKS) truststore holding the given certificates as trusted entries,
+/// readable by every JDK (`keytool -list -keystore <file>`).
+pub fn jks_truststore(entries: &[(String, String)], password: &str) -> Result<Vec<u8>> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&JKS_MAGIC.to_be_bytes());
+    out.extend_from_slice(&JKS_VERSION.to_be_bytes());
+    out.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+
+    for (alias, cert_pem) in entries {
+        let der = pem_to_der(cert_pem)?;
+        out.extend_from_slice(&JKS_TRUSTED_CERT_TAG.to_be_bytes());
+        // JKS aliases are case-insensitive and stored lowercased
+        write_java_utf(&mut out, &alias.to_lowercase())?;
+        out.extend_from_slice(&timestamp.to_be_bytes());
+        write_java_utf(&mut out, "X.509")?;
+        out.extend_from_slice(&(der.len() as u32).to_be_bytes());
+        out.extend_from_slice(&der);
+    }
+
+    // Integrity check: SHA-1 over the password (UTF-16BE), a fixed salt, and the keystore body
+    let mut digest = Sha1::new();
+    for unit in password.encode_utf16() {
+        digest.update(&unit.to_be_bytes());
+    }
+    digest.update(b"Mighty Aphrodite");
+    digest.update(&out);
+    out.extend_from_slice(&digest.finish());
+
+    Ok(out)
+}
+
+/// Java's `DataOutput.writeUTF`: u16 length prefix followed by the string bytes.
+/// Aliases and type names here are plain ASCII, where modified UTF-8 equals UTF-8.
+fn write_java_utf(out: &mut Vec<u8>, value: &str) -> Result<()> {
+    if !value.is_ascii() {
+        anyhow::bail!("Keystore alias must be ASCII: {}", value);
+    }
+    let len = u16::try_from(value.len()).context("Keystore alias too long")?;
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(value.as_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{CertificateParams, KeyPair};
+    use std::process::Command;
+
+    /// DER of pbeWithSHAAnd3-KeyTripleDES-CBC (1.2.840.113549.1.12.1.3)
+    const TRIPLE_DES_OID: &[u8] = &[
+        0x06, 0x0A, 0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x0C, 0x01, 0x03,
+    ];
+
+    /// A self-signed certificate for `name` and its key, as PEM.
+    fn cert(name: &str) -> (String, String) {
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec![name.to_string()])
+            .unwrap()
+            .self_signed(&key)
+            .unwrap();
+        (cert.pem(), key.serialize_pem())
+    }
+
+    fn read_u32(data: &[u8], pos: &mut usize) -> u32 {
+        let value = u32::from_be_bytes(data[*pos..*pos + 4].try_into().unwrap());
+        *pos += 4;
+        value
+    }
+
+    fn read_utf(data: &[u8], pos: &mut usize) -> String {
+        let len = u16::from_be_bytes([data[*pos], data[*pos + 1]]) as usize;
+        let value = String::from_utf8(data[*pos + 2..*pos + 2 + len].to_vec()).unwrap();
+        *pos += 2 + len;
+        value
+    }
+
+    /// Entries of a JKS truststore as (alias, DER), after checking its integrity digest.
+    fn read_jks(data: &[u8], password: &str) -> Vec<(String, Vec<u8>)> {
+        let (body, digest) = data.split_at(data.len() - 20);
+        let mut expected = Sha1::new();
+        for unit in password.encode_utf16() {
+            expected.update(&unit.to_be_bytes());
+        }
+        expected.update(b"Mighty Aphrodite");
+        expected.update(body);
+        assert_eq!(digest, expected.finish(), "keystore digest");
+
+        let mut pos = 0;
+        assert_eq!(read_u32(body, &mut pos), JKS_MAGIC);
+        assert_eq!(read_u32(body, &mut pos), JKS_VERSION);
+        let count = read_u32(body, &mut pos);
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            assert_eq!(read_u32(body, &mut pos), JKS_TRUSTED_CERT_TAG);
+            let alias = read_utf(body, &mut pos);
+            pos += 8; // creation time
+            assert_eq!(read_utf(body, &mut pos), "X.509");
+            let len = read_u32(body, &mut pos) as usize;
+            entries.push((alias, body[pos..pos + len].to_vec()));
+            pos += len;
+        }
+        assert_eq!(pos, body.len());
+        entries
+    }
+
+    #[test]
+    fn jks_truststore_round_trip() {
+        let (ca, _) = cert("DevRelay CA");
+        let (old_ca, _) = cert("Old CA");
+        let entries = vec![
+            ("DevRelay-CA".to_string(), ca.clone()),
+            ("old-ca".to_string(), old_ca.clone()),
+        ];
+        let jks = jks_truststore(&entries, DEFAULT_JKS_PASSWORD).unwrap();
+
+        assert_eq!(
+            read_jks(&jks, DEFAULT_JKS_PASSWORD),
+            vec![
+                ("devrelay-ca".to_string(), pem_to_der(&ca).unwrap()),
+                ("old-ca".to_string(), pem_to_der(&old_ca).unwrap()),
+            ]
+        );
+        assert!(jks_truststore(&[("caf\u{e9}".to_string(), ca)], "pw").is_err());
+    }
+
+    #[test]
+    fn jks_truststore_opens_in_keytool() {
+        if Command::new("keytool").arg("-help").output().is_err() {
+            eprintln!("keytool not found; skipping");
+            return;
+        }
+        let (ca, _) = cert("DevRelay CA");
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        let jks = jks_truststore(&[("devrelay-ca".to_string(), ca)], "s3cret-pw").unwrap();
+        std::io::Write::write_all(&mut file, &jks).unwrap();
+
+        let list = |password: &str| {
+            Command::new("keytool")
+                .args([
+                    "-list",
+                    "-storetype",
+                    "JKS",
+                    "-storepass",
+                    password,
+                    "-keystore",
+                ])
+                .arg(file.path())
+                .output()
+                .unwrap()
+        };
+        let output = list("s3cret-pw");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}", stdout);
+        assert!(stdout.contains("devrelay-ca"), "{}", stdout);
+        assert!(stdout.contains("trustedCertEntry"), "{}", stdout);
+        // keytool checks the integrity digest against the password
+        assert!(!list("wrong-pw").status.success());
+    }
+
+    #[test]
+    fn pkcs12_round_trip() {
+        let (leaf, key) = cert("app.test");
+        let (ca, _) = cert("DevRelay CA");
+        for legacy in [false, true] {
+            let der = pkcs12("app.test", &leaf, &key, &ca, "pw", legacy).unwrap();
+            let parsed = Pkcs12::from_der(&der).unwrap().parse2("pw").unwrap();
+            assert_eq!(
+                parsed.cert.unwrap().to_der().unwrap(),
+                pem_to_der(&leaf).unwrap()
+            );
+            assert_eq!(
+                parsed.pkey.unwrap().private_key_to_pkcs8().unwrap(),
+                key_pem_to_der(&key).unwrap()
+            );
+            let chain: Vec<Vec<u8>> = parsed
+                .ca
+                .unwrap()
+                .iter()
+                .map(|c| c.to_der().unwrap())
+                .collect();
+            assert_eq!(chain, vec![pem_to_der(&ca).unwrap()]);
+            assert!(Pkcs12::from_der(&der).unwrap().parse2("wrong").is_err());
+
+            let uses_3des = der
+                .windows(TRIPLE_DES_OID.len())
+                .any(|w| w == TRIPLE_DES_OID);
+            assert_eq!(uses_3des, legacy);
+        }
+    }
+}
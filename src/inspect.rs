@@ -0,0 +1,120 @@
+use crate::install;
+use anyhow::{Context, Result};
+use openssl::x509::X509;
+use serde::Serialize;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use x509_parser::extensions::GeneralName;
+use x509_parser::prelude::{FromDer, X509Certificate};
+use x509_parser::public_key::PublicKey;
+
+/// Details of a certificate file, as shown by `devrelay ca info`.
+#[derive(Serialize)]
+pub struct CertInfo {
+    pub path: PathBuf,
+    pub subject: String,
+    pub sans: Vec<String>,
+    pub issuer: String,
+    pub not_before: String,
+    pub not_after: String,
+    pub expired: bool,
+    pub fingerprint_sha256: String,
+    pub key_type: String,
+    pub is_ca: bool,
+    /// Signed by the current CA (for the current CA itself: always true).
+    pub chains_to_ca: bool,
+    /// Whether the system trust store has this certificate (CA certificates only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trusted: Option<bool>,
+}
+
+/// Output of `devrelay ca info --json`.
+#[derive(Serialize)]
+pub struct Report {
+    pub ca: Vec<CertInfo>,
+    pub certificates: Vec<CertInfo>,
+}
+
+/// Inspect the first certificate in a PEM file, checking it against the current CA.
+pub fn inspect(path: &Path, ca: Option<&X509>) -> Result<CertInfo> {
+    let pem = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let cert = X509::from_pem(&pem).context("Failed to parse certificate")?;
+    let der = cert
+        .to_der()
+        .context("Failed to encode certificate as DER")?;
+    let (_, parsed) = X509Certificate::from_der(&der)
+        .map_err(|e| anyhow::anyhow!("Failed to parse certificate: {}", e))?;
+
+    let sans = match parsed.subject_alternative_name() {
+        Ok(Some(san)) => san
+            .value
+            .general_names
+            .iter()
+            .filter_map(general_name)
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let fingerprint = install::cert_fingerprint_sha256(path)?
+        .context("No certificate in file")?
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":");
+
+    let chains_to_ca = match ca {
+        Some(ca) if ca.to_der().ok().as_deref() == Some(der.as_slice()) => true,
+        Some(ca) => {
+            let ca_public_key = ca.public_key().context("Failed to read CA public key")?;
+            cert.verify(&ca_public_key).unwrap_or(false)
+        }
+        None => false,
+    };
+
+    let validity = parsed.validity();
+    Ok(CertInfo {
+        path: path.to_path_buf(),
+        subject: parsed.subject().to_string(),
+        sans,
+        issuer: parsed.issuer().to_string(),
+        not_before: format_time(validity.not_before.to_datetime()),
+        not_after: format_time(validity.not_after.to_datetime()),
+        expired: validity.not_after.to_datetime() < OffsetDateTime::now_utc(),
+        fingerprint_sha256: fingerprint,
+        key_type: key_type(&parsed),
+        is_ca: parsed.is_ca(),
+        chains_to_ca,
+        trusted: None,
+    })
+}
+
+fn general_name(name: &GeneralName) -> Option<String> {
+    match name {
+        GeneralName::DNSName(dns) => Some(dns.to_string()),
+        GeneralName::IPAddress(bytes) => match bytes.len() {
+            4 => Some(IpAddr::from(<[u8; 4]>::try_from(*bytes).ok()?).to_string()),
+            16 => Some(IpAddr::from(<[u8; 16]>::try_from(*bytes).ok()?).to_string()),
+            _ => None,
+        },
+        GeneralName::RFC822Name(email) => Some(email.to_string()),
+        GeneralName::URI(uri) => Some(uri.to_string()),
+        _ => None,
+    }
+}
+
+fn key_type(cert: &X509Certificate) -> String {
+    let spki = cert.public_key();
+    match spki.parsed() {
+        Ok(PublicKey::RSA(rsa)) => format!("RSA {}", rsa.key_size()),
+        Ok(PublicKey::EC(point)) => format!("ECDSA P-{}", point.key_size()),
+        _ if spki.algorithm.algorithm.to_id_string() == "1.3.101.112" => "Ed25519".to_string(),
+        _ => spki.algorithm.algorithm.to_id_string(),
+    }
+}
+
+fn format_time(time: OffsetDateTime) -> String {
+    time.format(&Rfc3339).unwrap_or_else(|_| time.to_string())
+}
@@ -1,30 +1,185 @@
 use anyhow::{Context, Result};
 use sha2::Digest;
 use std::fs;
-use std::path::Path;
-use std::process::Command;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Prefix of the CA cert files we install into the system store on Linux. The legacy
+/// `devrelay-ca.crt` and the fingerprinted `devrelay-ca-<sha256 prefix>.crt` both match.
+const LINUX_CA_PREFIX: &str = "devrelay-ca";
+
+/// A Linux system trust store: where CA anchors go and how the store is refreshed afterwards.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LinuxTrustStore {
+    /// Debian/Ubuntu (`ca-certificates`)
+    Debian,
+    /// RHEL/Fedora (`ca-certificates`)
+    Fedora,
+    /// Arch Linux (`ca-certificates-utils`)
+    Arch,
+    /// openSUSE/SLES (`ca-certificates`)
+    OpenSuse,
+    /// Any other distribution with p11-kit's `trust` tool, which keeps the anchor files itself
+    P11Kit,
+}
+
+impl LinuxTrustStore {
+    /// Stores that take anchors as files in a directory, in detection order.
+    const ANCHOR_DIR_STORES: [Self; 4] = [Self::Debian, Self::Fedora, Self::Arch, Self::OpenSuse];
+
+    /// The trust store of this system, if it is one we know how to manage.
+    fn detect() -> Option<Self> {
+        Self::ANCHOR_DIR_STORES
+            .into_iter()
+            .find(|store| store.anchor_dir().is_some_and(|dir| Path::new(dir).is_dir()))
+            .or_else(|| {
+                Command::new("trust")
+                    .arg("--help")
+                    .output()
+                    .is_ok()
+                    .then_some(Self::P11Kit)
+            })
+    }
+
+    fn anchor_dir(self) -> Option<&'static str> {
+        match self {
+            Self::Debian => Some("/usr/local/share/ca-certificates"),
+            Self::Fedora => Some("/etc/pki/ca-trust/source/anchors"),
+            Self::Arch => Some("/etc/ca-certificates/trust-source/anchors"),
+            Self::OpenSuse => Some("/etc/pki/trust/anchors"),
+            Self::P11Kit => None,
+        }
+    }
+
+    fn update_command(self) -> Option<&'static str> {
+        match self {
+            Self::Debian | Self::OpenSuse => Some("update-ca-certificates"),
+            Self::Fedora | Self::Arch => Some("update-ca-trust"),
+            Self::P11Kit => None,
+        }
+    }
+
+    /// Add the CA certificate to the store; returns where it was put.
+    fn install(self, cert_path: &Path, fingerprint: &[u8; 32]) -> Result<String> {
+        let cert_path_str = cert_path
+            .canonicalize()
+            .context("Failed to resolve CA path")?
+            .to_str()
+            .context("Invalid cert path")?
+            .replace("'", "'\\''");
+
+        let Some(dest_dir) = self.anchor_dir() else {
+            println!("   Waiting for authentication...");
+            run_with_sudo(&format!("trust anchor --store '{}'", cert_path_str))
+                .context("Failed to add CA certificate with p11-kit")?;
+            return Ok("p11-kit trust store".to_string());
+        };
+
+        let dest_str = format!("{}/{}", dest_dir, anchor_file_name(fingerprint));
+        println!("   Waiting for authentication...");
+        let copy_cmd = format!("cp '{}' '{}'", cert_path_str, dest_str);
+        run_with_sudo(&copy_cmd).context("Failed to copy CA certificate")?;
+        if let Some(update_cmd) = self.update_command() {
+            run_with_sudo(update_cmd).context("Failed to update CA store")?;
+        }
+        Ok(dest_str)
+    }
+
+    /// Whether p11-kit lists the certificate as a trust anchor (anchor directories are checked
+    /// by file instead, see `linux_installed_ca_files`).
+    fn p11kit_has_anchor(fingerprint: &[u8; 32]) -> Result<bool> {
+        // In a private, randomly named directory, so no other user can plant or read the file
+        let dir = tempfile::Builder::new()
+            .prefix("devrelay-anchors-")
+            .tempdir()
+            .context("Failed to create a temporary directory")?;
+        let bundle = dir.path().join("anchors.pem");
+        let output = Command::new("trust")
+            .args(["extract", "--format=pem-bundle", "--filter=ca-anchors", "--overwrite"])
+            .arg(&bundle)
+            .output()
+            .context("Failed to run p11-kit trust")?;
+        let pem = if output.status.success() {
+            fs::read(&bundle).unwrap_or_default()
+        } else {
+            Vec::new()
+        };
 
-/// CA cert filename we install into the system store on Linux (so we can uninstall reliably).
-const LINUX_CA_FILENAME: &str = "devrelay-ca.crt";
+        let mut reader = std::io::Cursor::new(&pem);
+        while let Some(item) = rustls_pemfile::read_one(&mut reader).transpose() {
+            if let Ok(rustls_pemfile::Item::X509Certificate(der)) = item {
+                let fp: [u8; 32] = sha2::Sha256::digest(&der).into();
+                if fp == *fingerprint {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+}
 
-/// SHA-256 fingerprint of the first certificate in a PEM file (or PEM string).
-fn cert_fingerprint_sha256_from_pem(pem: &[u8]) -> Result<Option<[u8; 32]>> {
+/// DER of the first certificate in a PEM file (or PEM string).
+fn cert_der_from_pem(pem: &[u8]) -> Result<Option<Vec<u8>>> {
     let mut reader = std::io::Cursor::new(pem);
     for item in std::iter::from_fn(|| rustls_pemfile::read_one(&mut reader).transpose()) {
         let item = item.context("Failed to parse PEM")?;
         if let rustls_pemfile::Item::X509Certificate(der) = item {
-            return Ok(Some(sha2::Sha256::digest(&der).into()));
+            return Ok(Some(der.to_vec()));
         }
     }
     Ok(None)
 }
 
-fn cert_fingerprint_sha256(path: &Path) -> Result<Option<[u8; 32]>> {
+fn cert_der(path: &Path) -> Result<Option<Vec<u8>>> {
     let pem = fs::read(path).context("Failed to read CA cert file")?;
-    cert_fingerprint_sha256_from_pem(&pem)
+    cert_der_from_pem(&pem)
+}
+
+/// SHA-256 fingerprint of the first certificate in a PEM file.
+pub fn cert_fingerprint_sha256(path: &Path) -> Result<Option<[u8; 32]>> {
+    Ok(cert_der(path)?.map(|der| sha2::Sha256::digest(&der).into()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// File name for a CA in an anchor directory; fingerprinted so a rotated CA can sit next to the
+/// previous one.
+fn anchor_file_name(fingerprint: &[u8; 32]) -> String {
+    format!("{}-{}.crt", LINUX_CA_PREFIX, hex(&fingerprint[..4]).to_lowercase())
+}
+
+/// DevRelay CA files in the given anchor directories.
+fn installed_ca_files<P: AsRef<Path>>(dirs: impl IntoIterator<Item = P>) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for dir in dirs {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with(LINUX_CA_PREFIX) && name.ends_with(".crt") {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Installed DevRelay CA files in the Linux anchor directories.
+fn linux_installed_ca_files() -> Vec<PathBuf> {
+    installed_ca_files(
+        LinuxTrustStore::ANCHOR_DIR_STORES
+            .iter()
+            .filter_map(|store| store.anchor_dir()),
+    )
 }
 
-fn run_with_sudo(shell_command: &str) -> Result<String> {
+pub fn run_with_sudo(shell_command: &str) -> Result<String> {
     let output = Command::new("sudo")
         .args(["sh", "-c", shell_command])
         .output()
@@ -45,30 +200,145 @@ fn is_macos() -> bool {
     std::env::consts::OS == "macos"
 }
 
-pub struct Installer;
+/// NSS databases (`sql:` or legacy `dbm:` prefixed) that Firefox profiles and Chromium on Linux
+/// read instead of the system trust store.
+fn nss_databases() -> Vec<String> {
+    let Some(home) = std::env::var_os("HOME").map(PathBuf::from) else {
+        return Vec::new();
+    };
 
-impl Installer {
-    /// Install CA certificate to system trust store (macOS Keychain or Linux CA store).
-    pub fn install_ca_cert(cert_path: &Path) -> Result<bool> {
-        if !cert_path.exists() {
-            return Ok(false);
+    let mut dirs = Vec::new();
+    if !is_macos() {
+        dirs.push(home.join(".pki/nssdb"));
+    }
+    let profile_roots = if is_macos() {
+        vec![home.join("Library/Application Support/Firefox/Profiles")]
+    } else {
+        vec![
+            home.join(".mozilla/firefox"),
+            home.join("snap/firefox/common/.mozilla/firefox"),
+            home.join(".var/app/org.mozilla.firefox/.mozilla/firefox"),
+        ]
+    };
+    for root in profile_roots {
+        if let Ok(entries) = fs::read_dir(&root) {
+            let mut profiles: Vec<PathBuf> = entries.flatten().map(|e| e.path()).collect();
+            profiles.sort();
+            dirs.extend(profiles);
+        }
+    }
+
+    dirs.into_iter()
+        .filter_map(|dir| {
+            if dir.join("cert9.db").exists() {
+                Some(format!("sql:{}", dir.display()))
+            } else if dir.join("cert8.db").exists() {
+                Some(format!("dbm:{}", dir.display()))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Path to NSS `certutil`, if installed (Homebrew's nss is keg-only on macOS).
+fn certutil_path() -> Option<PathBuf> {
+    let candidates = [
+        "certutil",
+        "/opt/homebrew/opt/nss/bin/certutil",
+        "/usr/local/opt/nss/bin/certutil",
+    ];
+    candidates.iter().map(PathBuf::from).find(|path| {
+        Command::new(path)
+            .arg("-H")
+            .output()
+            .is_ok_and(|output| String::from_utf8_lossy(&output.stderr).contains("-A"))
+    })
+}
+
+/// NSS nickname for a CA: its common name plus a fingerprint prefix, so a rotated CA gets its own entry.
+fn nss_nickname(der: &[u8]) -> Result<String> {
+    let cert = openssl::x509::X509::from_der(der).context("Failed to parse CA certificate")?;
+    let common_name = cert
+        .subject_name()
+        .entries_by_nid(openssl::nid::Nid::COMMONNAME)
+        .next()
+        .and_then(|entry| entry.data().as_utf8().ok())
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| "DevRelay CA".to_string());
+    let fingerprint: [u8; 32] = sha2::Sha256::digest(der).into();
+    Ok(format!("{} {}", common_name, hex(&fingerprint[..4]).to_lowercase()))
+}
+
+/// Whether the NSS database has the certificate with this nickname and fingerprint.
+fn nss_has_cert(certutil: &Path, db: &str, nickname: &str, der: &[u8]) -> bool {
+    let output = Command::new(certutil)
+        .args(["-L", "-d", db, "-n", nickname, "-a"])
+        .output();
+    match output {
+        Ok(output) if output.status.success() => {
+            cert_der_from_pem(&output.stdout).ok().flatten().as_deref() == Some(der)
+        }
+        _ => false,
+    }
+}
+
+/// A store of trusted CA certificates. Certificates are matched by fingerprint, so a store can
+/// hold several DevRelay CAs at once (e.g. while `devrelay ca rotate` overlaps old and new).
+pub trait TrustStore {
+    /// Add the CA certificate at `cert_path`; returns false if it could not be installed.
+    fn install(&self, cert_path: &Path) -> Result<bool>;
+
+    /// Whether the store has this exact CA certificate.
+    fn is_installed(&self, cert_path: &Path) -> Result<bool>;
+
+    /// Remove this exact CA certificate; returns false if it could not be removed.
+    fn uninstall(&self, cert_path: &Path) -> Result<bool>;
+}
+
+/// The hosts file route hosts are resolved through.
+pub trait HostsFile {
+    fn path(&self) -> &Path;
+
+    fn read(&self) -> Result<String>;
+
+    /// Replace the contents of the file atomically, keeping the previous contents in
+    /// [`hosts_backup_path`].
+    fn write(&self, contents: &str) -> Result<()>;
+}
+
+/// The trust stores of this machine: macOS System Keychain or the Linux system store, plus the
+/// NSS databases of Firefox and Chromium. Changes go through `sudo`.
+pub struct SystemTrustStore {
+    /// Common name of the CA, used to look it up in the macOS keychain.
+    ca_name: String,
+}
+
+impl SystemTrustStore {
+    pub fn new(ca_name: &str) -> Self {
+        Self {
+            ca_name: ca_name.to_string(),
         }
+    }
+
+    /// Check if the CA certificate at cert_path is installed in system trust store.
+    /// On macOS we verify by fingerprint so a stale cert with the same name is not considered installed.
+    fn is_installed_system(&self, cert_path: &Path) -> Result<bool> {
+        let our_fp = match cert_fingerprint_sha256(cert_path)? {
+            Some(f) => f,
+            None => return Ok(false),
+        };
         if is_macos() {
-            Self::install_ca_cert_macos(cert_path)
+            self.is_installed_macos(&our_fp)
         } else if std::env::consts::OS == "linux" {
-            Self::install_ca_cert_linux(cert_path)
+            Self::is_installed_linux(&our_fp)
         } else {
-            println!("⚠️  CA certificate auto-install is not supported on this OS.");
-            println!(
-                "   Add the CA cert to your system trust store manually: {}",
-                cert_path.display()
-            );
             Ok(false)
         }
     }
 
     /// Install CA certificate to macOS System Keychain.
-    fn install_ca_cert_macos(cert_path: &Path) -> Result<bool> {
+    fn install_macos(cert_path: &Path) -> Result<bool> {
         println!("🔐 Installing CA certificate to macOS Keychain...");
         println!("   Waiting for authentication...");
 
@@ -99,219 +369,166 @@ impl Installer {
         }
     }
 
-    /// Install CA certificate to Linux system trust store (Debian/Ubuntu or RHEL/Fedora).
-    fn install_ca_cert_linux(cert_path: &Path) -> Result<bool> {
-        let cert_path_str = cert_path
-            .canonicalize()
-            .context("Failed to resolve CA path")?
-            .to_str()
-            .context("Invalid cert path")?
-            .replace("'", "'\\''");
-
-        // Prefer Debian/Ubuntu path; fallback to RHEL/Fedora.
-        let (dest_dir, update_cmd) = if Path::new("/usr/local/share/ca-certificates").exists() {
-            ("/usr/local/share/ca-certificates", "update-ca-certificates")
-        } else if Path::new("/etc/pki/ca-trust/source/anchors").exists() {
-            ("/etc/pki/ca-trust/source/anchors", "update-ca-trust")
-        } else {
+    /// Install CA certificate to Linux system trust store (Debian/Ubuntu, RHEL/Fedora, Arch,
+    /// openSUSE, or p11-kit's `trust` elsewhere).
+    fn install_linux(cert_path: &Path) -> Result<bool> {
+        let Some(store) = LinuxTrustStore::detect() else {
             eprintln!(
-                "❌ No supported CA store found. Install ca-certificates (Debian/Ubuntu) or ca-certificates (RHEL/Fedora)."
+                "❌ No supported CA store found. Install ca-certificates (Debian/Ubuntu, RHEL/Fedora, openSUSE), ca-certificates-utils (Arch) or p11-kit."
             );
             return Ok(false);
         };
-
-        let dest_str = format!("{}/{}", dest_dir, LINUX_CA_FILENAME);
+        let fingerprint = cert_fingerprint_sha256(cert_path)?.context("No certificate in CA file")?;
 
         println!("🔐 Installing CA certificate to system trust store...");
-        println!("   Target: {}", dest_str);
-        println!("   Waiting for authentication...");
-
-        let copy_cmd = format!("cp '{}' '{}'", cert_path_str, dest_str);
-        run_with_sudo(&copy_cmd).context("Failed to copy CA certificate")?;
-
-        run_with_sudo(update_cmd).context("Failed to update CA store")?;
+        let target = store.install(cert_path, &fingerprint)?;
+        println!("   Target: {}", target);
 
         println!("✅ CA certificate installed successfully!");
         Ok(true)
     }
 
-    /// Add domain entries to /etc/hosts (macOS and Linux).
-    pub fn install_hosts_entries(domains: &[String]) -> Result<bool> {
-        if domains.is_empty() {
+    /// Add the CA certificate to every NSS database that does not have it yet. Without certutil
+    /// this only warns, as the databases are optional (see `is_installed_nss`).
+    fn install_nss(cert_path: &Path) -> Result<bool> {
+        let databases = nss_databases();
+        if databases.is_empty() {
             return Ok(true);
         }
-
-        println!("\n🌐 Updating /etc/hosts with domain entries...");
-
-        // Read current /etc/hosts
-        let hosts_content =
-            fs::read_to_string("/etc/hosts").context("Failed to read /etc/hosts")?;
-
-        // Check which domains are missing
-        let mut missing_domains = Vec::new();
-        for domain in domains {
-            if !Self::is_domain_in_hosts(&hosts_content, domain) {
-                missing_domains.push(domain.clone());
-            }
-        }
-
-        if missing_domains.is_empty() {
-            println!("✅ All domains already in /etc/hosts");
+        let Some(certutil) = certutil_path() else {
+            println!("⚠️  Firefox/Chromium certificate databases found, but certutil is not installed.");
+            println!(
+                "   Install libnss3-tools (Debian/Ubuntu), nss-tools (Fedora) or nss (Arch, Homebrew), then run with --force-install."
+            );
             return Ok(true);
-        }
+        };
+        let der = cert_der(cert_path)?.context("No certificate in CA file")?;
+        let nickname = nss_nickname(&der)?;
 
-        // Create new entries
-        let mut new_entries = String::from("\n# DevRelay entries\n");
-        for domain in &missing_domains {
-            new_entries.push_str(&format!("127.0.0.1 {}\n", domain));
+        let missing: Vec<&String> = databases
+            .iter()
+            .filter(|db| !nss_has_cert(&certutil, db, &nickname, &der))
+            .collect();
+        if missing.is_empty() {
+            return Ok(true);
         }
 
-        println!("   Waiting for authentication...");
-
-        let entries_str = new_entries.replace("'", "'\\''");
-        let command = format!("echo '{}' >> /etc/hosts", entries_str.trim());
-
-        match run_with_sudo(&command) {
-            Ok(_) => {
-                println!(
-                    "✅ Added {} domain(s) to /etc/hosts:",
-                    missing_domains.len()
+        println!("🦊 Installing CA certificate to Firefox/Chromium databases...");
+        let cert_path_str = cert_path.to_str().context("Invalid cert path")?;
+        let mut success = true;
+        for db in missing {
+            let output = Command::new(&certutil)
+                .args(["-A", "-d", db, "-t", "C,,", "-n", &nickname, "-i", cert_path_str])
+                .output()
+                .context("Failed to run certutil")?;
+            if output.status.success() {
+                println!("   ✓ {}", db);
+            } else {
+                eprintln!(
+                    "❌ Failed to add CA certificate to {}: {}",
+                    db,
+                    String::from_utf8_lossy(&output.stderr).trim()
                 );
-                for domain in &missing_domains {
-                    println!("   • {}", domain);
-                }
-                Ok(true)
-            }
-            Err(e) => {
-                let error_msg = e.to_string();
-                if error_msg.contains("user cancelled") {
-                    println!("⚠️  Installation cancelled by user");
-                    Ok(false)
-                } else {
-                    eprintln!("❌ Failed to update /etc/hosts: {}", error_msg);
-                    Ok(false)
-                }
-            }
-        }
-    }
-
-    /// Check if a domain is already in /etc/hosts pointing to 127.0.0.1
-    fn is_domain_in_hosts(hosts_content: &str, domain: &str) -> bool {
-        for line in hosts_content.lines() {
-            let line = line.trim();
-
-            // Skip comments and empty lines
-            if line.is_empty() || line.starts_with('#') {
-                continue;
-            }
-
-            // Check if line contains "127.0.0.1" and the domain
-            if line.starts_with("127.0.0.1") && line.contains(domain) {
-                // Verify it's the exact domain, not a substring
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() >= 2 && parts[1..].contains(&domain) {
-                    return true;
-                }
+                success = false;
             }
         }
-        false
-    }
-
-    /// Remove CA certificate from system trust store (macOS Keychain or Linux).
-    pub fn uninstall_ca_cert(ca_name: &str) -> Result<bool> {
-        if is_macos() {
-            Self::uninstall_ca_cert_macos(ca_name)
-        } else if std::env::consts::OS == "linux" {
-            Self::uninstall_ca_cert_linux()
-        } else {
-            println!("⚠️  CA certificate uninstall is not supported on this OS.");
-            Ok(false)
+        if success {
+            println!("✅ CA certificate installed to Firefox/Chromium (restart the browser)");
         }
+        Ok(success)
     }
 
-    /// Remove CA certificate from macOS System Keychain.
-    fn uninstall_ca_cert_macos(ca_name: &str) -> Result<bool> {
+    /// Remove the CA certificate with the given SHA-1 hash from macOS System Keychain.
+    fn uninstall_macos(&self, sha1: &[u8; 20]) -> Result<bool> {
         println!("🔐 Removing CA certificate from macOS Keychain...");
 
         let find_output = Command::new("security")
             .arg("find-certificate")
             .arg("-a")
             .arg("-c")
-            .arg(ca_name)
+            .arg(&self.ca_name)
             .arg("-Z")
             .arg("/Library/Keychains/System.keychain")
             .output()
             .context("Failed to search keychain")?;
 
-        if !find_output.status.success() || find_output.stdout.is_empty() {
-            println!("✅ CA certificate not found in keychain (already removed)");
-            return Ok(true);
-        }
-
+        let hash = hex(sha1);
         let stdout = String::from_utf8_lossy(&find_output.stdout);
-        let hashes: Vec<&str> = stdout
+        let installed = stdout
             .lines()
             .filter(|line| line.starts_with("SHA-1 hash:"))
             .filter_map(|line| line.split(':').nth(1).map(|h| h.trim()))
-            .collect();
+            .any(|h| h.eq_ignore_ascii_case(&hash));
 
-        if hashes.is_empty() {
+        if !find_output.status.success() || !installed {
             println!("✅ CA certificate not found in keychain (already removed)");
             return Ok(true);
         }
 
         println!("   Waiting for authentication...");
 
-        let mut success = true;
-        for hash in &hashes {
-            let command = format!(
-                "security delete-certificate -Z {} /Library/Keychains/System.keychain",
-                hash
-            );
-
-            if let Err(e) = run_with_sudo(&command) {
+        let command = format!(
+            "security delete-certificate -Z {} /Library/Keychains/System.keychain",
+            hash
+        );
+        match run_with_sudo(&command) {
+            Ok(_) => {
+                println!("✅ CA certificate removed from keychain");
+                Ok(true)
+            }
+            Err(e) => {
                 let error_msg = e.to_string();
                 if error_msg.contains("user cancelled") {
                     println!("⚠️  Uninstallation cancelled by user");
-                    return Ok(false);
+                } else {
+                    eprintln!(
+                        "❌ Failed to remove certificate (hash {}): {}",
+                        hash, error_msg
+                    );
                 }
-                eprintln!(
-                    "❌ Failed to remove certificate (hash {}): {}",
-                    hash, error_msg
-                );
-                success = false;
+                Ok(false)
             }
         }
-
-        if success {
-            println!("✅ CA certificate removed from keychain");
-        }
-        Ok(success)
     }
 
-    /// Remove CA certificate from Linux system trust store.
-    fn uninstall_ca_cert_linux() -> Result<bool> {
+    /// Remove the CA certificate with the given fingerprint from Linux system trust store.
+    fn uninstall_linux(cert_path: &Path, fingerprint: &[u8; 32]) -> Result<bool> {
         println!("🔐 Removing CA certificate from system trust store...");
 
-        let anchors = [
-            "/usr/local/share/ca-certificates",
-            "/etc/pki/ca-trust/source/anchors",
-        ];
         let mut removed = false;
-        for dir in &anchors {
-            let path = format!("{}/{}", dir, LINUX_CA_FILENAME);
-            if Path::new(&path).exists() {
+        let mut updated_stores = Vec::new();
+        for path in linux_installed_ca_files() {
+            if cert_fingerprint_sha256(&path).ok().flatten() != Some(*fingerprint) {
+                continue;
+            }
+            if !removed {
                 println!("   Waiting for authentication...");
-                let cmd = format!("rm -f '{}'", path.replace("'", "'\\''"));
-                run_with_sudo(&cmd).context("Failed to remove CA certificate")?;
-                removed = true;
+            }
+            let path_str = path.to_str().context("Invalid cert path")?;
+            let cmd = format!("rm -f '{}'", path_str.replace("'", "'\\''"));
+            run_with_sudo(&cmd).context("Failed to remove CA certificate")?;
+            removed = true;
+            if let Some(store) = LinuxTrustStore::ANCHOR_DIR_STORES
+                .into_iter()
+                .find(|store| store.anchor_dir().is_some_and(|dir| path.starts_with(dir)))
+                && !updated_stores.contains(&store)
+            {
+                updated_stores.push(store);
+            }
+        }
+        for store in updated_stores {
+            if let Some(update_cmd) = store.update_command() {
+                let _ = run_with_sudo(update_cmd);
             }
         }
 
-        if Path::new("/usr/local/share/ca-certificates").exists() {
-            let _ = run_with_sudo("update-ca-certificates");
-        } else if Path::new("/etc/pki/ca-trust/source/anchors").exists() {
-            let _ = run_with_sudo("update-ca-trust");
+        if LinuxTrustStore::detect() == Some(LinuxTrustStore::P11Kit)
+            && LinuxTrustStore::p11kit_has_anchor(fingerprint)?
+        {
+            let path_str = cert_path.to_str().context("Invalid cert path")?;
+            let cmd = format!("trust anchor --remove '{}'", path_str.replace("'", "'\\''"));
+            run_with_sudo(&cmd).context("Failed to remove CA certificate with p11-kit")?;
+            removed = true;
         }
 
         if removed {
@@ -322,73 +539,706 @@ impl Installer {
         Ok(true)
     }
 
-    /// Remove DevRelay domain entries from /etc/hosts (macOS and Linux).
-    pub fn uninstall_hosts_entries(domains: &[String]) -> Result<bool> {
-        if domains.is_empty() {
+    /// Remove the CA certificate from every NSS database that has it.
+    fn uninstall_nss(der: &[u8]) -> Result<bool> {
+        let databases = nss_databases();
+        let Some(certutil) = certutil_path().filter(|_| !databases.is_empty()) else {
             return Ok(true);
-        }
-
-        println!("\n🌐 Removing DevRelay entries from /etc/hosts...");
+        };
+        let nickname = nss_nickname(der)?;
 
-        let hosts_content =
-            fs::read_to_string("/etc/hosts").context("Failed to read /etc/hosts")?;
+        let mut success = true;
+        for db in databases
+            .iter()
+            .filter(|db| nss_has_cert(&certutil, db, &nickname, der))
+        {
+            let output = Command::new(&certutil)
+                .args(["-D", "-d", db, "-n", &nickname])
+                .output()
+                .context("Failed to run certutil")?;
+            if output.status.success() {
+                println!("✅ CA certificate removed from {}", db);
+            } else {
+                eprintln!(
+                    "❌ Failed to remove CA certificate from {}: {}",
+                    db,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+                success = false;
+            }
+        }
+        Ok(success)
+    }
 
-        let mut removed = Vec::new();
-        let mut in_devrelay_block = false;
-        let filtered: Vec<&str> = hosts_content
-            .lines()
-            .filter(|line| {
-                let trimmed = line.trim();
+    /// Check if our exact CA certificate (by fingerprint) is in macOS System Keychain.
+    fn is_installed_macos(&self, our_fingerprint: &[u8; 32]) -> Result<bool> {
+        let output = Command::new("security")
+            .arg("find-certificate")
+            .arg("-a")
+            .arg("-c")
+            .arg(&self.ca_name)
+            .arg("-p")
+            .arg("/Library/Keychains/System.keychain")
+            .output()
+            .context("Failed to check keychain")?;
 
-                // Track the "# DevRelay entries" comment block
-                if trimmed == "# DevRelay entries" {
-                    in_devrelay_block = true;
-                    return false;
-                }
+        if !output.status.success() || output.stdout.is_empty() {
+            return Ok(false);
+        }
 
-                if in_devrelay_block {
-                    if trimmed.starts_with("127.0.0.1") {
-                        let parts: Vec<&str> = trimmed.split_whitespace().collect();
-                        if parts.len() >= 2
-                            && domains.iter().any(|d| parts[1..].contains(&d.as_str()))
-                        {
-                            removed.push(parts[1..].join(" "));
-                            return false;
-                        }
-                    }
-                    // Non-matching line ends the block
-                    if !trimmed.is_empty() {
-                        in_devrelay_block = false;
-                    } else {
-                        return false;
-                    }
+        // Keychain may return multiple certs with the same name (e.g. old + new). Check if any match.
+        let pem = output.stdout;
+        let mut reader = std::io::Cursor::new(&pem);
+        while let Some(item) = rustls_pemfile::read_one(&mut reader).transpose() {
+            let item = item.context("Failed to parse keychain PEM")?;
+            if let rustls_pemfile::Item::X509Certificate(der) = item {
+                let fp: [u8; 32] = sha2::Sha256::digest(&der).into();
+                if fp == *our_fingerprint {
+                    return Ok(true);
                 }
+            }
+        }
+        Ok(false)
+    }
 
-                true
-            })
-            .collect();
+    /// Check if our exact CA certificate (by fingerprint) is in Linux trust store.
+    fn is_installed_linux(our_fingerprint: &[u8; 32]) -> Result<bool> {
+        for path in linux_installed_ca_files() {
+            if cert_fingerprint_sha256(&path)? == Some(*our_fingerprint) {
+                return Ok(true);
+            }
+        }
+        if LinuxTrustStore::detect() == Some(LinuxTrustStore::P11Kit) {
+            return LinuxTrustStore::p11kit_has_anchor(our_fingerprint);
+        }
+        Ok(false)
+    }
 
-        if removed.is_empty() {
-            println!("✅ No DevRelay entries found in /etc/hosts");
+    /// Whether every NSS database has the CA certificate. Without certutil there is nothing
+    /// we can do about them, so they don't count.
+    fn is_installed_nss(cert_path: &Path) -> Result<bool> {
+        let databases = nss_databases();
+        let Some(certutil) = certutil_path().filter(|_| !databases.is_empty()) else {
             return Ok(true);
-        }
+        };
+        let der = cert_der(cert_path)?.context("No certificate in CA file")?;
+        let nickname = nss_nickname(&der)?;
+        Ok(databases
+            .iter()
+            .all(|db| nss_has_cert(&certutil, db, &nickname, &der)))
+    }
 
-        println!("   Waiting for authentication...");
+}
 
-        let new_content = filtered.join("\n") + "\n";
-        let content_escaped = new_content.replace("'", "'\\''").replace("\"", "\\\"");
+impl TrustStore for SystemTrustStore {
+    /// Install to the system trust store (macOS Keychain or Linux CA store) and to the NSS
+    /// databases of Firefox and Chromium.
+    fn install(&self, cert_path: &Path) -> Result<bool> {
+        let system = if self.is_installed_system(cert_path)? {
+            true
+        } else if is_macos() {
+            Self::install_macos(cert_path)?
+        } else if std::env::consts::OS == "linux" {
+            Self::install_linux(cert_path)?
+        } else {
+            println!("⚠️  CA certificate auto-install is not supported on this OS.");
+            println!(
+                "   Add the CA cert to your system trust store manually: {}",
+                cert_path.display()
+            );
+            false
+        };
+        let nss = Self::install_nss(cert_path)?;
+        Ok(system && nss)
+    }
 
-        // Use printf instead of echo to better handle special characters
-        let command = format!(
-            "printf '%s' '{}' | tee /etc/hosts > /dev/null",
-            content_escaped
-        );
+    fn is_installed(&self, cert_path: &Path) -> Result<bool> {
+        Ok(self.is_installed_system(cert_path)? && Self::is_installed_nss(cert_path)?)
+    }
 
-        match run_with_sudo(&command) {
+    fn uninstall(&self, cert_path: &Path) -> Result<bool> {
+        let der = cert_der(cert_path)?.context("No certificate in CA file")?;
+        let system = if is_macos() {
+            self.uninstall_macos(&openssl::sha::sha1(&der))?
+        } else if std::env::consts::OS == "linux" {
+            Self::uninstall_linux(cert_path, &sha2::Sha256::digest(&der).into())?
+        } else {
+            println!("⚠️  CA certificate uninstall is not supported on this OS.");
+            false
+        };
+        let nss = Self::uninstall_nss(&der)?;
+        Ok(system && nss)
+    }
+}
+
+/// Where the previous contents of a hosts file are kept when it is rewritten.
+pub fn hosts_backup_path(hosts_path: &Path) -> PathBuf {
+    hosts_path.with_extension("devrelay.bak")
+}
+
+/// Temporary file next to the hosts file, so renaming it over the hosts file is atomic.
+fn hosts_temp_path(hosts_path: &Path) -> PathBuf {
+    hosts_path.with_extension("devrelay.tmp")
+}
+
+/// `/etc/hosts`, written through `sudo`.
+pub struct SystemHostsFile;
+
+impl HostsFile for SystemHostsFile {
+    fn path(&self) -> &Path {
+        Path::new("/etc/hosts")
+    }
+
+    fn read(&self) -> Result<String> {
+        fs::read_to_string(self.path()).context("Failed to read /etc/hosts")
+    }
+
+    fn write(&self, contents: &str) -> Result<()> {
+        let hosts = self.path().display().to_string();
+        let backup = hosts_backup_path(self.path()).display().to_string();
+        let temp = hosts_temp_path(self.path()).display().to_string();
+        // The new contents arrive on stdin; the temp file starts as a copy to keep owner and
+        // mode. Renaming fails where /etc/hosts is a bind mount (e.g. in containers); overwrite
+        // it in place there instead.
+        let script = format!(
+            "cp -p '{hosts}' '{backup}' && cp -p '{hosts}' '{temp}' && cat > '{temp}' && \
+             {{ mv -f '{temp}' '{hosts}' 2>/dev/null || {{ cat '{temp}' > '{hosts}' && rm -f '{temp}'; }}; }}"
+        );
+
+        println!("   Waiting for authentication...");
+        let mut child = Command::new("sudo")
+            .args(["sh", "-c", &script])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to execute sudo command")?;
+        child
+            .stdin
+            .take()
+            .context("Failed to open sudo stdin")?
+            .write_all(contents.as_bytes())
+            .context("Failed to write /etc/hosts")?;
+        let output = child.wait_with_output().context("Failed to execute sudo command")?;
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("Command failed: {}", error.trim());
+        }
+        Ok(())
+    }
+}
+
+/// A trust store that keeps CA certificates as files in an anchor directory under `root`, laid
+/// out like the Debian/Ubuntu store. Needs no privileges and runs no update command, which makes
+/// it suitable for tests and for images and chroots that are built offline.
+pub struct FsTrustStore {
+    anchor_dir: PathBuf,
+}
+
+impl FsTrustStore {
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        let anchor_dir = LinuxTrustStore::Debian
+            .anchor_dir()
+            .map(|dir| root.as_ref().join(dir.trim_start_matches('/')))
+            .unwrap_or_else(|| root.as_ref().to_path_buf());
+        Self { anchor_dir }
+    }
+
+    fn files_with(&self, fingerprint: &[u8; 32]) -> Vec<PathBuf> {
+        installed_ca_files([&self.anchor_dir])
+            .into_iter()
+            .filter(|path| cert_fingerprint_sha256(path).ok().flatten() == Some(*fingerprint))
+            .collect()
+    }
+}
+
+impl TrustStore for FsTrustStore {
+    fn install(&self, cert_path: &Path) -> Result<bool> {
+        let fingerprint = cert_fingerprint_sha256(cert_path)?.context("No certificate in CA file")?;
+        fs::create_dir_all(&self.anchor_dir).with_context(|| {
+            format!("Failed to create {}", self.anchor_dir.display())
+        })?;
+        let dest = self.anchor_dir.join(anchor_file_name(&fingerprint));
+        fs::copy(cert_path, &dest)
+            .with_context(|| format!("Failed to copy CA certificate to {}", dest.display()))?;
+        println!("✅ CA certificate installed to {}", dest.display());
+        Ok(true)
+    }
+
+    fn is_installed(&self, cert_path: &Path) -> Result<bool> {
+        let fingerprint = cert_fingerprint_sha256(cert_path)?.context("No certificate in CA file")?;
+        Ok(!self.files_with(&fingerprint).is_empty())
+    }
+
+    fn uninstall(&self, cert_path: &Path) -> Result<bool> {
+        let fingerprint = cert_fingerprint_sha256(cert_path)?.context("No certificate in CA file")?;
+        for path in self.files_with(&fingerprint) {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+            println!("✅ CA certificate removed from {}", path.display());
+        }
+        Ok(true)
+    }
+}
+
+/// A hosts file at `etc/hosts` under `root`, written directly.
+pub struct FsHostsFile {
+    path: PathBuf,
+}
+
+impl FsHostsFile {
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self {
+            path: root.as_ref().join("etc/hosts"),
+        }
+    }
+}
+
+impl HostsFile for FsHostsFile {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn read(&self) -> Result<String> {
+        match fs::read_to_string(&self.path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+            result => result.with_context(|| format!("Failed to read {}", self.path.display())),
+        }
+    }
+
+    fn write(&self, contents: &str) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let temp = hosts_temp_path(&self.path);
+        if self.path.exists() {
+            let backup = hosts_backup_path(&self.path);
+            fs::copy(&self.path, &backup)
+                .with_context(|| format!("Failed to back up to {}", backup.display()))?;
+            // Start from a copy so the permissions carry over
+            fs::copy(&self.path, &temp)
+                .with_context(|| format!("Failed to write {}", temp.display()))?;
+        }
+        fs::write(&temp, contents)
+            .with_context(|| format!("Failed to write {}", temp.display()))?;
+        fs::rename(&temp, &self.path)
+            .with_context(|| format!("Failed to replace {}", self.path.display()))
+    }
+}
+
+const HOSTS_BLOCK_BEGIN: &str = "# BEGIN devrelay";
+const HOSTS_BLOCK_END: &str = "# END devrelay";
+/// Starts the entries of one project inside the block, up to the next marker.
+const HOSTS_PROJECT_MARKER: &str = "# project: ";
+/// Header of the blocks older versions appended; their entries move into the managed block.
+const LEGACY_HOSTS_HEADER: &str = "# DevRelay entries";
+
+/// A name and the addresses it resolves to in the hosts file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HostEntry {
+    pub name: String,
+    pub addresses: Vec<IpAddr>,
+}
+
+/// The entries of one project in the managed block, or those without a project marker (from
+/// older versions) when `project` is `None`.
+struct HostsSection {
+    project: Option<String>,
+    entries: Vec<HostEntry>,
+}
+
+/// A hosts file split into the lines DevRelay does not own and the entries in its managed
+/// `# BEGIN devrelay` ... `# END devrelay` block, grouped by the project that wrote them.
+struct HostsBlock {
+    lines: Vec<String>,
+    /// Index in `lines` where the block is rendered: where it (or a legacy block) was found.
+    position: usize,
+    sections: Vec<HostsSection>,
+}
+
+impl HostsBlock {
+    fn parse(content: &str) -> Result<Self> {
+        let mut block = Self {
+            lines: Vec::new(),
+            position: 0,
+            sections: Vec::new(),
+        };
+        let mut position = None;
+        let mut iter = content.lines().peekable();
+        while let Some(line) = iter.next() {
+            let trimmed = line.trim();
+            if trimmed == HOSTS_BLOCK_BEGIN {
+                position.get_or_insert(block.lines.len());
+                let mut project = None;
+                loop {
+                    let Some(line) = iter.next() else {
+                        anyhow::bail!(
+                            "\"{}\" without \"{}\" in hosts file; fix it manually",
+                            HOSTS_BLOCK_BEGIN,
+                            HOSTS_BLOCK_END
+                        );
+                    };
+                    let trimmed = line.trim();
+                    if trimmed == HOSTS_BLOCK_END {
+                        break;
+                    }
+                    if let Some(name) = trimmed.strip_prefix(HOSTS_PROJECT_MARKER) {
+                        project = Some(name.trim().to_string());
+                    } else if !trimmed.is_empty() && !trimmed.starts_with('#') {
+                        block.add_entry(project.as_deref(), trimmed);
+                    }
+                }
+            } else if trimmed == LEGACY_HOSTS_HEADER {
+                // Legacy blocks are the header and the 127.0.0.1 lines right after it
+                position.get_or_insert(block.lines.len());
+                while let Some(entry) = iter.next_if(|l| l.trim().starts_with("127.0.0.1")) {
+                    block.add_entry(None, entry.trim());
+                }
+            } else {
+                block.lines.push(line.to_string());
+            }
+        }
+
+        block.position = position.unwrap_or(block.lines.len());
+        Ok(block)
+    }
+
+    /// The entries of `project`, added as an empty section if it has none yet.
+    fn entries_mut(&mut self, project: Option<&str>) -> &mut Vec<HostEntry> {
+        let index = match self.sections.iter().position(|s| s.project.as_deref() == project) {
+            Some(index) => index,
+            None => {
+                self.sections.push(HostsSection {
+                    project: project.map(str::to_string),
+                    entries: Vec::new(),
+                });
+                self.sections.len() - 1
+            }
+        };
+        &mut self.sections[index].entries
+    }
+
+    /// Names `project` has entries for.
+    fn names(&self, project: Option<&str>) -> Vec<String> {
+        self.sections
+            .iter()
+            .filter(|s| s.project.as_deref() == project)
+            .flat_map(|s| s.entries.iter().map(|e| e.name.clone()))
+            .collect()
+    }
+
+    /// Collect an `<ip> <name>...` line into the entries of `project`.
+    fn add_entry(&mut self, project: Option<&str>, line: &str) {
+        let mut parts = line.split_whitespace();
+        let Some(ip) = parts.next().and_then(|ip| ip.parse::<IpAddr>().ok()) else {
+            return;
+        };
+        let entries = self.entries_mut(project);
+        for name in parts {
+            match entries.iter_mut().find(|e| e.name == name) {
+                Some(entry) if !entry.addresses.contains(&ip) => entry.addresses.push(ip),
+                Some(_) => {}
+                None => entries.push(HostEntry {
+                    name: name.to_string(),
+                    addresses: vec![ip],
+                }),
+            }
+        }
+    }
+
+    /// Replace the entries of `project` with `entries`. Their names are taken over from other
+    /// sections, so the project synced last wins.
+    fn replace(&mut self, project: Option<&str>, entries: Vec<HostEntry>) {
+        for section in &mut self.sections {
+            section
+                .entries
+                .retain(|e| !entries.iter().any(|entry| entry.name == e.name));
+        }
+        *self.entries_mut(project) = entries;
+    }
+
+    /// Whether a line outside the block maps `domain` to one of `addresses`.
+    fn has_unmanaged_entry(&self, domain: &str, addresses: &[IpAddr]) -> bool {
+        self.lines.iter().any(|line| {
+            let mut parts = line.split_whitespace();
+            parts
+                .next()
+                .and_then(|ip| ip.parse::<IpAddr>().ok())
+                .is_some_and(|ip| addresses.contains(&ip))
+                && parts.any(|name| name == domain)
+        })
+    }
+
+    /// The hosts file with the block rewritten from the sections, one line per name and address
+    /// after each project marker, or left out if there are no entries.
+    fn render(&self) -> String {
+        let mut out: Vec<String> = self.lines[..self.position].to_vec();
+        let mut sections: Vec<&HostsSection> =
+            self.sections.iter().filter(|s| !s.entries.is_empty()).collect();
+        // Entries without a project come first, as they have no marker
+        sections.sort_by_key(|s| s.project.is_some());
+        if !sections.is_empty() {
+            out.push(HOSTS_BLOCK_BEGIN.to_string());
+            for section in sections {
+                if let Some(project) = &section.project {
+                    out.push(format!("{}{}", HOSTS_PROJECT_MARKER, project));
+                }
+                for entry in &section.entries {
+                    out.extend(entry.addresses.iter().map(|ip| format!("{} {}", ip, entry.name)));
+                }
+            }
+            out.push(HOSTS_BLOCK_END.to_string());
+        }
+        out.extend_from_slice(&self.lines[self.position..]);
+        if out.is_empty() {
+            String::new()
+        } else {
+            out.join("\n") + "\n"
+        }
+    }
+}
+
+/// Installs the CA and hosts entries into a trust store and a hosts file.
+pub struct Installer {
+    trust_store: Box<dyn TrustStore>,
+    hosts_file: Box<dyn HostsFile>,
+    /// Marks this project's entries in the hosts block (e.g. its cert_dir)
+    project: Option<String>,
+}
+
+impl Installer {
+    pub fn new(trust_store: impl TrustStore + 'static, hosts_file: impl HostsFile + 'static) -> Self {
+        Self {
+            trust_store: Box::new(trust_store),
+            hosts_file: Box::new(hosts_file),
+            project: None,
+        }
+    }
+
+    /// Keep the hosts entries under a `# project: <project>` marker, so each sync replaces
+    /// exactly this project's entries and leaves those of other projects alone.
+    pub fn with_project(mut self, project: impl Into<String>) -> Self {
+        self.project = Some(project.into());
+        self
+    }
+
+    /// The trust stores and `/etc/hosts` of this machine.
+    pub fn system(ca_name: &str) -> Self {
+        Self::new(SystemTrustStore::new(ca_name), SystemHostsFile)
+    }
+
+    /// Install the CA certificate into the trust store.
+    pub fn install_ca_cert(&self, cert_path: &Path) -> Result<bool> {
+        if !cert_path.exists() {
+            return Ok(false);
+        }
+        self.trust_store.install(cert_path)
+    }
+
+    /// Import the CA certificate into a JDK's `cacerts`. Messages go to stderr, so this can run
+    /// from `devrelay env` while its output is being evaluated by the shell.
+    pub fn install_ca_cert_jdk(cert_path: &Path, java_home: &Path) -> Result<bool> {
+        let keytool = java_home.join("bin/keytool");
+        let Some(cacerts) = ["lib/security/cacerts", "jre/lib/security/cacerts"]
+            .iter()
+            .map(|p| java_home.join(p))
+            .find(|p| p.exists())
+        else {
+            anyhow::bail!("No cacerts found in JDK {}", java_home.display());
+        };
+        let fingerprint = cert_fingerprint_sha256(cert_path)?.context("No certificate in CA file")?;
+        let alias = format!("{}-{}", LINUX_CA_PREFIX, hex(&fingerprint[..4]).to_lowercase());
+        let password = crate::export::DEFAULT_JKS_PASSWORD;
+
+        let listed = Command::new(&keytool)
+            .args(["-list", "-alias", &alias, "-storepass", password, "-keystore"])
+            .arg(&cacerts)
+            .output()
+            .with_context(|| format!("Failed to run {}", keytool.display()))?;
+        if listed.status.success() {
+            eprintln!("✅ CA certificate already in {}", cacerts.display());
+            return Ok(true);
+        }
+
+        let quote = |path: &Path| format!("'{}'", path.to_string_lossy().replace("'", "'\\''"));
+        let command = format!(
+            "{} -importcert -noprompt -alias {} -storepass {} -keystore {} -file {}",
+            quote(&keytool),
+            alias,
+            password,
+            quote(&cacerts),
+            quote(&cert_path.canonicalize().context("Failed to resolve CA path")?)
+        );
+        eprintln!("☕ Importing CA certificate into {}...", cacerts.display());
+        let writable = fs::OpenOptions::new().append(true).open(&cacerts).is_ok();
+        let result = if writable {
+            let output = Command::new("sh")
+                .args(["-c", &command])
+                .output()
+                .context("Failed to run keytool")?;
+            if output.status.success() {
+                Ok(String::new())
+            } else {
+                Err(anyhow::anyhow!(
+                    "{}",
+                    String::from_utf8_lossy(&output.stdout).trim()
+                ))
+            }
+        } else {
+            eprintln!("   Waiting for authentication...");
+            run_with_sudo(&command)
+        };
+
+        match result {
+            Ok(_) => {
+                eprintln!("✅ CA certificate imported (alias {})", alias);
+                Ok(true)
+            }
+            Err(e) => {
+                eprintln!("❌ Failed to import CA certificate: {}", e);
+                Ok(false)
+            }
+        }
+    }
+
+    /// Sync this project's entries in the managed block of the hosts file to `entries`: the
+    /// project's previous entries are replaced, so names no longer listed are removed.
+    pub fn install_hosts_entries(&self, entries: &[HostEntry]) -> Result<bool> {
+        let hosts_content = self.hosts_file.read()?;
+        let mut block = HostsBlock::parse(&hosts_content)?;
+        let project = self.project.as_deref();
+        let previous = block.names(project);
+
+        let mut synced: Vec<HostEntry> = Vec::new();
+        for entry in entries {
+            let domain = &entry.name;
+            // IP address hosts (e.g. a LAN IP route) need no name resolution, and wildcards
+            // cannot be expressed in a hosts file (the built-in DNS server answers for them)
+            if domain.parse::<IpAddr>().is_ok() || domain.starts_with("*.") {
+                continue;
+            }
+            if synced.iter().any(|e| &e.name == domain)
+                || block.has_unmanaged_entry(domain, &entry.addresses)
+            {
+                continue;
+            }
+            synced.push(entry.clone());
+        }
+        let added: Vec<String> = synced
+            .iter()
+            .map(|e| e.name.clone())
+            .filter(|name| !previous.contains(name))
+            .collect();
+        let removed: Vec<String> = previous
+            .iter()
+            .filter(|name| !synced.iter().any(|e| &e.name == *name))
+            .cloned()
+            .collect();
+        block.replace(project, synced);
+
+        // Also rewrites legacy blocks and duplicate markers
+        let new_content = block.render();
+        if entries.is_empty() && new_content == hosts_content {
+            return Ok(true);
+        }
+
+        let hosts_path = self.hosts_file.path().display();
+        println!("\n🌐 Updating {} with domain entries...", hosts_path);
+        if new_content == hosts_content {
+            println!("✅ All domains already in {}", hosts_path);
+            return Ok(true);
+        }
+
+        match self.hosts_file.write(&new_content) {
+            Ok(_) => {
+                if added.is_empty() && removed.is_empty() {
+                    println!("✅ Updated DevRelay entries in {}", hosts_path);
+                }
+                if !added.is_empty() {
+                    println!("✅ Added {} domain(s) to {}:", added.len(), hosts_path);
+                    for domain in &added {
+                        println!("   • {}", domain);
+                    }
+                }
+                if !removed.is_empty() {
+                    println!(
+                        "✅ Removed {} domain(s) no longer configured from {}:",
+                        removed.len(),
+                        hosts_path
+                    );
+                    for domain in &removed {
+                        println!("   • {}", domain);
+                    }
+                }
+                Ok(true)
+            }
+            Err(e) => {
+                let error_msg = e.to_string();
+                if error_msg.contains("user cancelled") {
+                    println!("⚠️  Installation cancelled by user");
+                    Ok(false)
+                } else {
+                    eprintln!("❌ Failed to update {}: {}", hosts_path, error_msg);
+                    Ok(false)
+                }
+            }
+        }
+    }
+
+    /// Remove the CA certificate at `cert_path` from the trust store.
+    /// Only the certificate with this exact fingerprint is removed, never others with the same name.
+    pub fn uninstall_ca_cert(&self, cert_path: &Path) -> Result<bool> {
+        if !cert_path.exists() {
+            println!(
+                "⚠️  CA certificate not found at {}; remove it from the trust store manually.",
+                cert_path.display()
+            );
+            return Ok(false);
+        }
+        self.trust_store.uninstall(cert_path)
+    }
+
+    /// Remove this project's entries from the managed block of the hosts file, and those for
+    /// `domains` that have no project marker (written by older versions). Entries of other
+    /// projects stay; the block goes away with its last entry.
+    pub fn uninstall_hosts_entries(&self, domains: &[String]) -> Result<bool> {
+        let hosts_content = self.hosts_file.read()?;
+        let mut block = HostsBlock::parse(&hosts_content)?;
+        let project = self.project.as_deref();
+        if domains.is_empty() && block.names(project).is_empty() {
+            return Ok(true);
+        }
+
+        let hosts_path = self.hosts_file.path().display();
+        println!("\n🌐 Removing DevRelay entries from {}...", hosts_path);
+
+        let mut removed = Vec::new();
+        for section in &mut block.sections {
+            let owned = section.project.is_some() && section.project.as_deref() == project;
+            section.entries.retain(|entry| {
+                let remove =
+                    owned || (section.project.is_none() && domains.contains(&entry.name));
+                if remove && !removed.contains(&entry.name) {
+                    removed.push(entry.name.clone());
+                }
+                !remove
+            });
+        }
+
+        if removed.is_empty() {
+            println!("✅ No DevRelay entries found in {}", hosts_path);
+            return Ok(true);
+        }
+
+        let new_content = block.render();
+
+        match self.hosts_file.write(&new_content) {
             Ok(_) => {
                 println!(
-                    "✅ Removed {} domain entry/entries from /etc/hosts:",
-                    removed.len()
+                    "✅ Removed {} domain entry/entries from {}:",
+                    removed.len(),
+                    hosts_path
                 );
                 for entry in &removed {
                     println!("   • {}", entry);
@@ -401,26 +1251,28 @@ impl Installer {
                     println!("⚠️  Uninstallation cancelled by user");
                     Ok(false)
                 } else {
-                    eprintln!("❌ Failed to update /etc/hosts: {}", error_msg);
+                    eprintln!("❌ Failed to update {}: {}", hosts_path, error_msg);
                     Ok(false)
                 }
             }
         }
     }
 
-    /// Run the full uninstallation process
-    pub fn run_uninstall(ca_name: &str, domains: &[String]) -> Result<()> {
+    /// Remove this project's hosts entries, and the CA certificate at `cert_path` if given.
+    pub fn run_uninstall(&self, cert_path: Option<&Path>, domains: &[String]) -> Result<()> {
         println!("\n╔════════════════════════════════════════╗");
         println!("║     DevRelay Uninstallation           ║");
         println!("╚════════════════════════════════════════╝\n");
 
         let mut success = true;
 
-        if !Self::uninstall_ca_cert(ca_name)? {
+        if let Some(cert_path) = cert_path
+            && !self.uninstall_ca_cert(cert_path)?
+        {
             success = false;
         }
 
-        if !Self::uninstall_hosts_entries(domains)? {
+        if !self.uninstall_hosts_entries(domains)? {
             success = false;
         }
 
@@ -433,88 +1285,26 @@ impl Installer {
         Ok(())
     }
 
-    /// Check if the CA certificate at cert_path is installed in system trust store.
-    /// On macOS we verify by fingerprint so a stale cert with the same name is not considered installed.
-    pub fn is_ca_installed(cert_path: &Path, ca_name: &str) -> Result<bool> {
+    /// Check if the CA certificate at cert_path is installed in the trust store (by fingerprint).
+    pub fn is_ca_installed(&self, cert_path: &Path) -> Result<bool> {
         if !cert_path.exists() {
             return Ok(false);
         }
-        let our_fp = match cert_fingerprint_sha256(cert_path)? {
-            Some(f) => f,
-            None => return Ok(false),
-        };
-        if is_macos() {
-            Self::is_ca_installed_macos(ca_name, &our_fp)
-        } else if std::env::consts::OS == "linux" {
-            Self::is_ca_installed_linux(&our_fp)
-        } else {
-            Ok(false)
-        }
-    }
-
-    /// Check if our exact CA certificate (by fingerprint) is in macOS System Keychain.
-    fn is_ca_installed_macos(ca_name: &str, our_fingerprint: &[u8; 32]) -> Result<bool> {
-        let output = Command::new("security")
-            .arg("find-certificate")
-            .arg("-a")
-            .arg("-c")
-            .arg(ca_name)
-            .arg("-p")
-            .arg("/Library/Keychains/System.keychain")
-            .output()
-            .context("Failed to check keychain")?;
-
-        if !output.status.success() || output.stdout.is_empty() {
-            return Ok(false);
-        }
-
-        // Keychain may return multiple certs with the same name (e.g. old + new). Check if any match.
-        let pem = output.stdout;
-        let mut reader = std::io::Cursor::new(&pem);
-        while let Some(item) = rustls_pemfile::read_one(&mut reader).transpose() {
-            let item = item.context("Failed to parse keychain PEM")?;
-            if let rustls_pemfile::Item::X509Certificate(der) = item {
-                let fp: [u8; 32] = sha2::Sha256::digest(&der).into();
-                if fp == *our_fingerprint {
-                    return Ok(true);
-                }
-            }
-        }
-        Ok(false)
-    }
-
-    /// Check if our exact CA certificate (by fingerprint) is in Linux trust store.
-    fn is_ca_installed_linux(our_fingerprint: &[u8; 32]) -> Result<bool> {
-        let anchors = [
-            "/usr/local/share/ca-certificates",
-            "/etc/pki/ca-trust/source/anchors",
-        ];
-        for dir in &anchors {
-            let path = format!("{}/{}", dir, LINUX_CA_FILENAME);
-            if Path::new(&path).exists() {
-                if let Some(installed) = cert_fingerprint_sha256(Path::new(&path))? {
-                    return Ok(installed == *our_fingerprint);
-                }
-            }
-        }
-        Ok(false)
+        self.trust_store.is_installed(cert_path)
     }
 
     /// Run the full installation process
-    pub fn run_install(cert_path: &Path, ca_name: &str, domains: &[String]) -> Result<()> {
+    pub fn run_install(&self, cert_path: &Path, entries: &[HostEntry]) -> Result<()> {
         println!("\n╔════════════════════════════════════════╗");
         println!("║     DevRelay Installation Setup       ║");
         println!("╚════════════════════════════════════════╝\n");
 
         let mut success = true;
 
-        // Install CA certificate (on macOS, replace any existing cert with same name so we don't leave a stale one)
-        if !Self::is_ca_installed(cert_path, ca_name)? {
-            if is_macos() {
-                // Remove any existing "DevRelay CA" (or same ca_name) so the new cert replaces it
-                let _ = Self::uninstall_ca_cert(ca_name)?;
-            }
-            if !Self::install_ca_cert(cert_path)? {
+        // Install CA certificate. Other certificates with the same name are left alone: they may
+        // belong to another project's CA, and a rotated-out CA is removed by `devrelay ca rotate`.
+        if !self.is_ca_installed(cert_path)? {
+            if !self.install_ca_cert(cert_path)? {
                 success = false;
             }
         } else {
@@ -522,7 +1312,7 @@ impl Installer {
         }
 
         // Install hosts entries
-        if !Self::install_hosts_entries(domains)? {
+        if !self.install_hosts_entries(entries)? {
             success = false;
         }
 
@@ -535,3 +1325,398 @@ impl Installer {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{BasicConstraints, CertificateParams, DistinguishedName, DnType, IsCa, KeyPair};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A fresh directory under the system temp dir, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let path = std::env::temp_dir().join(format!(
+                "devrelay-install-test-{}-{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        fn path(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Write a new self-signed CA certificate named `ca_name` to `dir/file_name`.
+    fn write_ca(dir: &Path, file_name: &str, ca_name: &str) -> PathBuf {
+        let mut params = CertificateParams::default();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let mut dn = DistinguishedName::new();
+        dn.push(DnType::CommonName, ca_name);
+        params.distinguished_name = dn;
+        let key_pair = KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key_pair).unwrap();
+        let path = dir.join(file_name);
+        fs::write(&path, cert.pem()).unwrap();
+        path
+    }
+
+    fn installer(root: &Path) -> Installer {
+        Installer::new(FsTrustStore::new(root), FsHostsFile::new(root))
+    }
+
+    fn anchor_files(root: &Path) -> Vec<PathBuf> {
+        installed_ca_files([root.join("usr/local/share/ca-certificates")])
+    }
+
+    fn hosts(root: &Path) -> String {
+        fs::read_to_string(root.join("etc/hosts")).unwrap()
+    }
+
+    fn domains(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    /// Entries for `names` on 127.0.0.1 and ::1.
+    fn entries(names: &[&str]) -> Vec<HostEntry> {
+        entries_on(names, &["127.0.0.1", "::1"])
+    }
+
+    fn entries_on(names: &[&str], addresses: &[&str]) -> Vec<HostEntry> {
+        names
+            .iter()
+            .map(|name| HostEntry {
+                name: name.to_string(),
+                addresses: addresses.iter().map(|ip| ip.parse().unwrap()).collect(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn installs_checks_and_uninstalls_ca() {
+        let root = TempDir::new();
+        let ca = write_ca(root.path(), "ca.crt", "DevRelay Test CA");
+        let installer = installer(root.path());
+
+        assert!(!installer.is_ca_installed(&ca).unwrap());
+        assert!(installer.install_ca_cert(&ca).unwrap());
+        assert!(installer.is_ca_installed(&ca).unwrap());
+
+        let files = anchor_files(root.path());
+        assert_eq!(files.len(), 1);
+        assert_eq!(fs::read(&files[0]).unwrap(), fs::read(&ca).unwrap());
+
+        // Installing again keeps a single copy
+        assert!(installer.install_ca_cert(&ca).unwrap());
+        assert_eq!(anchor_files(root.path()).len(), 1);
+
+        assert!(installer.uninstall_ca_cert(&ca).unwrap());
+        assert!(!installer.is_ca_installed(&ca).unwrap());
+        assert!(anchor_files(root.path()).is_empty());
+    }
+
+    #[test]
+    fn uninstall_keeps_other_ca_with_same_name() {
+        let root = TempDir::new();
+        let old_ca = write_ca(root.path(), "ca.old.crt", "DevRelay Test CA");
+        let new_ca = write_ca(root.path(), "ca.crt", "DevRelay Test CA");
+        let installer = installer(root.path());
+
+        assert!(installer.install_ca_cert(&old_ca).unwrap());
+        assert!(installer.install_ca_cert(&new_ca).unwrap());
+        assert_eq!(anchor_files(root.path()).len(), 2);
+
+        assert!(installer.uninstall_ca_cert(&old_ca).unwrap());
+        assert!(!installer.is_ca_installed(&old_ca).unwrap());
+        assert!(installer.is_ca_installed(&new_ca).unwrap());
+    }
+
+    #[test]
+    fn missing_ca_file_is_not_installed() {
+        let root = TempDir::new();
+        let missing = root.path().join("ca.crt");
+        let installer = installer(root.path());
+
+        assert!(!installer.is_ca_installed(&missing).unwrap());
+        assert!(!installer.install_ca_cert(&missing).unwrap());
+        assert!(!installer.uninstall_ca_cert(&missing).unwrap());
+        assert!(anchor_files(root.path()).is_empty());
+    }
+
+    #[test]
+    fn adds_missing_hosts_entries_once() {
+        let root = TempDir::new();
+        let hosts_file = FsHostsFile::new(root.path());
+        hosts_file
+            .write("127.0.0.1 localhost\n127.0.0.1 api.test")
+            .unwrap();
+        let installer = installer(root.path());
+
+        let names = entries(&["app.test", "api.test", "192.168.1.20", "app.test"]);
+        assert!(installer.install_hosts_entries(&names).unwrap());
+        assert_eq!(
+            hosts(root.path()),
+            concat!(
+                "127.0.0.1 localhost\n",
+                "127.0.0.1 api.test\n",
+                "# BEGIN devrelay\n",
+                "127.0.0.1 app.test\n",
+                "::1 app.test\n",
+                "# END devrelay\n",
+            )
+        );
+
+        assert!(installer.install_hosts_entries(&names).unwrap());
+        assert_eq!(hosts(root.path()).matches("127.0.0.1 app.test").count(), 1);
+    }
+
+    #[test]
+    fn removes_only_devrelay_hosts_entries() {
+        let root = TempDir::new();
+        let hosts_file = FsHostsFile::new(root.path());
+        hosts_file
+            .write("127.0.0.1 localhost\n127.0.0.1 other.test\n")
+            .unwrap();
+        let installer = installer(root.path());
+        let names = domains(&["app.test", "api.test"]);
+
+        assert!(installer.install_hosts_entries(&entries(&["app.test", "api.test"])).unwrap());
+        assert!(installer.uninstall_hosts_entries(&names).unwrap());
+        assert_eq!(
+            hosts(root.path()),
+            "127.0.0.1 localhost\n127.0.0.1 other.test\n"
+        );
+    }
+
+    #[test]
+    fn keeps_entries_of_other_projects_in_block() {
+        let root = TempDir::new();
+        let one = installer(root.path()).with_project("/src/one/certs");
+        let two = installer(root.path()).with_project("/src/two/certs");
+
+        assert!(one.install_hosts_entries(&entries(&["one.test"])).unwrap());
+        assert!(two
+            .install_hosts_entries(&entries_on(&["two.test"], &["127.0.0.2"]))
+            .unwrap());
+        assert!(one.install_hosts_entries(&entries(&["one.test"])).unwrap());
+        assert_eq!(
+            hosts(root.path()),
+            concat!(
+                "# BEGIN devrelay\n",
+                "# project: /src/one/certs\n",
+                "127.0.0.1 one.test\n",
+                "::1 one.test\n",
+                "# project: /src/two/certs\n",
+                "127.0.0.2 two.test\n",
+                "# END devrelay\n",
+            )
+        );
+
+        assert!(one.uninstall_hosts_entries(&domains(&["one.test"])).unwrap());
+        assert_eq!(
+            hosts(root.path()),
+            "# BEGIN devrelay\n# project: /src/two/certs\n127.0.0.2 two.test\n# END devrelay\n"
+        );
+    }
+
+    #[test]
+    fn sync_removes_entries_of_dropped_routes() {
+        let root = TempDir::new();
+        let one = installer(root.path()).with_project("/src/one/certs");
+        let two = installer(root.path()).with_project("/src/two/certs");
+
+        assert!(one.install_hosts_entries(&entries(&["app.test", "api.test"])).unwrap());
+        assert!(two.install_hosts_entries(&entries(&["two.test"])).unwrap());
+
+        // api.test is no longer configured
+        assert!(one.install_hosts_entries(&entries(&["app.test"])).unwrap());
+        let content = hosts(root.path());
+        assert!(!content.contains("api.test"), "{}", content);
+        assert!(content.contains("127.0.0.1 app.test"));
+        assert!(content.contains("127.0.0.1 two.test"));
+
+        // No entries left (e.g. the DNS server took over), so the section goes away
+        assert!(one.install_hosts_entries(&[]).unwrap());
+        assert_eq!(
+            hosts(root.path()),
+            concat!(
+                "# BEGIN devrelay\n",
+                "# project: /src/two/certs\n",
+                "127.0.0.1 two.test\n",
+                "::1 two.test\n",
+                "# END devrelay\n",
+            )
+        );
+    }
+
+    #[test]
+    fn project_takes_over_unmarked_entries() {
+        let root = TempDir::new();
+        let hosts_file = FsHostsFile::new(root.path());
+        hosts_file
+            .write("# BEGIN devrelay\n127.0.0.1 app.test\n127.0.0.1 old.test\n# END devrelay\n")
+            .unwrap();
+        let installer = installer(root.path()).with_project("/src/app/certs");
+
+        assert!(installer
+            .install_hosts_entries(&entries_on(&["app.test"], &["127.0.0.1"]))
+            .unwrap());
+        assert_eq!(
+            hosts(root.path()),
+            concat!(
+                "# BEGIN devrelay\n",
+                "127.0.0.1 old.test\n",
+                "# project: /src/app/certs\n",
+                "127.0.0.1 app.test\n",
+                "# END devrelay\n",
+            )
+        );
+
+        // Unmarked entries are only removed by name
+        assert!(installer.uninstall_hosts_entries(&domains(&["old.test"])).unwrap());
+        assert_eq!(hosts(root.path()), "");
+    }
+
+    #[test]
+    fn updates_addresses_of_existing_entries() {
+        let root = TempDir::new();
+        let installer = installer(root.path());
+
+        assert!(installer.install_hosts_entries(&entries(&["app.test"])).unwrap());
+        assert!(installer
+            .install_hosts_entries(&entries_on(&["app.test"], &["127.0.0.3"]))
+            .unwrap());
+        assert_eq!(
+            hosts(root.path()),
+            "# BEGIN devrelay\n127.0.0.3 app.test\n# END devrelay\n"
+        );
+    }
+
+    #[test]
+    fn adds_entry_mapped_elsewhere_outside_block() {
+        let root = TempDir::new();
+        let hosts_file = FsHostsFile::new(root.path());
+        hosts_file.write("127.0.0.1 app.test\n").unwrap();
+        let installer = installer(root.path());
+
+        assert!(installer
+            .install_hosts_entries(&entries_on(&["app.test"], &["127.0.0.2"]))
+            .unwrap());
+        assert_eq!(
+            hosts(root.path()),
+            "127.0.0.1 app.test\n# BEGIN devrelay\n127.0.0.2 app.test\n# END devrelay\n"
+        );
+    }
+
+    #[test]
+    fn migrates_legacy_entries_into_block() {
+        let root = TempDir::new();
+        let hosts_file = FsHostsFile::new(root.path());
+        hosts_file
+            .write(concat!(
+                "127.0.0.1 localhost\n",
+                "# DevRelay entries\n",
+                "127.0.0.1 app.test\n",
+                "\n",
+                "# DevRelay entries\n",
+                "127.0.0.1 api.test\n",
+                "10.0.0.5 nas.lan\n",
+            ))
+            .unwrap();
+        let installer = installer(root.path()).with_project("/src/app/certs");
+
+        assert!(installer.install_hosts_entries(&entries(&["app.test"])).unwrap());
+        assert_eq!(
+            hosts(root.path()),
+            concat!(
+                "127.0.0.1 localhost\n",
+                "# BEGIN devrelay\n",
+                "127.0.0.1 api.test\n",
+                "# project: /src/app/certs\n",
+                "127.0.0.1 app.test\n",
+                "::1 app.test\n",
+                "# END devrelay\n",
+                "\n",
+                "10.0.0.5 nas.lan\n",
+            )
+        );
+    }
+
+    #[test]
+    fn uninstall_keeps_blank_lines_and_other_entries() {
+        let root = TempDir::new();
+        let hosts_file = FsHostsFile::new(root.path());
+        let original = "127.0.0.1 localhost\n\n# other tool\n127.0.0.1 app.test\n\n";
+        hosts_file.write(original).unwrap();
+        let installer = installer(root.path());
+
+        // Already mapped outside the block, so nothing to add
+        assert!(installer.install_hosts_entries(&entries(&["app.test", "api.test"])).unwrap());
+        assert!(installer.uninstall_hosts_entries(&domains(&["app.test", "api.test"])).unwrap());
+        assert_eq!(hosts(root.path()), original);
+    }
+
+    #[test]
+    fn writes_backup_of_previous_hosts_file() {
+        let root = TempDir::new();
+        let hosts_file = FsHostsFile::new(root.path());
+        hosts_file.write("127.0.0.1 localhost\n").unwrap();
+        let installer = installer(root.path());
+
+        assert!(installer.install_hosts_entries(&entries(&["app.test"])).unwrap());
+        let backup = hosts_backup_path(hosts_file.path());
+        assert_eq!(fs::read_to_string(backup).unwrap(), "127.0.0.1 localhost\n");
+        assert!(!hosts_temp_path(hosts_file.path()).exists());
+    }
+
+    #[test]
+    fn unterminated_block_is_left_alone() {
+        let root = TempDir::new();
+        let hosts_file = FsHostsFile::new(root.path());
+        let original = "# BEGIN devrelay\n127.0.0.1 app.test\n127.0.0.1 mine.test\n";
+        hosts_file.write(original).unwrap();
+        let installer = installer(root.path());
+
+        assert!(installer.install_hosts_entries(&entries(&["api.test"])).is_err());
+        assert_eq!(hosts(root.path()), original);
+    }
+
+    #[test]
+    fn run_install_and_uninstall_round_trip() {
+        let root = TempDir::new();
+        let ca = write_ca(root.path(), "ca.crt", "DevRelay Test CA");
+        let installer = installer(root.path());
+        let names = domains(&["app.test"]);
+
+        installer.run_install(&ca, &entries(&["app.test"])).unwrap();
+        assert!(installer.is_ca_installed(&ca).unwrap());
+        assert!(hosts(root.path()).contains("127.0.0.1 app.test"));
+
+        installer.run_uninstall(Some(&ca), &names).unwrap();
+        assert!(!installer.is_ca_installed(&ca).unwrap());
+        assert!(!hosts(root.path()).contains("app.test"));
+    }
+
+    #[test]
+    fn run_uninstall_keeps_ca_unless_given() {
+        let root = TempDir::new();
+        let ca = write_ca(root.path(), "ca.crt", "DevRelay Test CA");
+        let installer = installer(root.path());
+
+        installer.run_install(&ca, &entries(&["app.test"])).unwrap();
+        installer.run_uninstall(None, &domains(&["app.test"])).unwrap();
+        assert!(installer.is_ca_installed(&ca).unwrap());
+        assert!(!hosts(root.path()).contains("app.test"));
+    }
+}
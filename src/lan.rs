@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+use qrcode::QrCode;
+use qrcode::render::unicode::Dense1x2;
+use std::net::IpAddr;
+
+/// A network interface address to expose the proxy on.
+pub struct LanAddress {
+    pub interface: String,
+    /// Interface index, for IPv6 multicast
+    pub index: u32,
+    pub ip: IpAddr,
+}
+
+/// Address of `interface`, or of the first interface on a private network when none is named.
+/// IPv4 addresses win, since they are what phones on the same Wi-Fi can type and reach.
+pub fn lan_address(interface: Option<&str>) -> Result<LanAddress> {
+    let mut candidates: Vec<if_addrs::Interface> = if_addrs::get_if_addrs()
+        .context("Failed to list network interfaces")?
+        .into_iter()
+        .filter(|iface| !iface.is_loopback() && !iface.is_link_local())
+        .filter(|iface| interface.is_none_or(|name| iface.name == name))
+        .collect();
+    // Stable sort: IPv4 first, then private networks, keeping the system's interface order
+    candidates.sort_by_key(|iface| (iface.ip().is_ipv6(), !is_private(iface.ip())));
+
+    match candidates.into_iter().next() {
+        Some(iface) => Ok(LanAddress {
+            ip: iface.ip(),
+            index: iface.index.unwrap_or(0),
+            interface: iface.name,
+        }),
+        None => match interface {
+            Some(name) => anyhow::bail!("Interface {} has no usable address", name),
+            None => anyhow::bail!(
+                "No network interface with a LAN address found; pass one with --lan <INTERFACE>"
+            ),
+        },
+    }
+}
+
+/// Whether `ip` is on a private network (RFC 1918, or an IPv6 unique local address).
+fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_private(),
+        IpAddr::V6(ip) => (ip.segments()[0] & 0xfe00) == 0xfc00,
+    }
+}
+
+/// URL of a listener on `ip`, leaving out the scheme's default port.
+pub fn url(ip: IpAddr, port: u16, tls: bool) -> String {
+    match ip {
+        IpAddr::V4(ip) => host_url(&ip.to_string(), port, tls),
+        IpAddr::V6(ip) => host_url(&format!("[{}]", ip), port, tls),
+    }
+}
+
+/// URL of `host` on a listener port, leaving out the scheme's default port.
+pub fn host_url(host: &str, port: u16, tls: bool) -> String {
+    let scheme = if tls { "https" } else { "http" };
+    match (tls, port) {
+        (true, 443) | (false, 80) => format!("{}://{}/", scheme, host),
+        _ => format!("{}://{}:{}/", scheme, host, port),
+    }
+}
+
+/// `data` as a QR code drawn with Unicode half blocks, for scanning off the terminal.
+pub fn qr_code(data: &str) -> Result<String> {
+    let code = QrCode::new(data.as_bytes()).context("Failed to encode QR code")?;
+    // Light modules are drawn as blocks, which reads right on the usual dark terminal background
+    Ok(code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build())
+}
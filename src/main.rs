@@ -1,16 +1,32 @@
+mod acme;
 mod certs;
 mod config;
+mod dns;
+mod export;
+mod inspect;
 mod install;
+mod lan;
+mod mdns;
 mod proxy;
+mod tls;
+mod verify;
 
+use acme::AcmeServer;
 use anyhow::{Context, Result};
 use certs::CertManager;
-use clap::Parser;
-use config::Config;
-use install::Installer;
+use clap::{Parser, Subcommand, ValueEnum};
+use config::{AddressFamily, Config};
+use install::{FsHostsFile, FsTrustStore, HostEntry, Installer};
+use openssl::x509::X509;
+use pingora_core::listeners::TcpSocketOptions;
+use pingora_core::services::background::background_service;
 use proxy::{DevRelayProxy, get_listen_addresses};
-use std::path::PathBuf;
+use std::fs;
+use std::io::{IsTerminal, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use tls::{CertRotation, ListenerCert};
 
 #[derive(Parser, Debug)]
 #[command(name = "devrelay")]
@@ -31,14 +47,256 @@ enum Command {
         #[arg(long)]
         force_install: bool,
 
-        /// Uninstall CA certificate and /etc/hosts entries, then exit
+        /// Remove this project's /etc/hosts entries, then exit. The CA stays trusted, since other
+        /// projects may share it
         #[arg(long)]
         uninstall: bool,
 
+        /// With --uninstall, also remove the CA certificate from the trust store
+        #[arg(long, requires = "uninstall")]
+        remove_ca: bool,
+
+        /// Install the CA cert and hosts entries into the file tree under DIR (e.g. a container
+        /// image root) instead of this machine's trust stores and /etc/hosts
+        #[arg(long, value_name = "DIR")]
+        install_root: Option<PathBuf>,
+
+        /// Also listen on a LAN interface (the first one on a private network unless named), add
+        /// its address to the certificate, announce `.local` hosts over mDNS, and print URLs and
+        /// QR codes for other devices
+        #[arg(long, value_name = "INTERFACE")]
+        lan: Option<Option<String>>,
+
         /// Suppress per-request "Proxying ... -> ..." log lines
         #[arg(short, long)]
         quiet: bool,
     },
+
+    /// Issue certificates from the DevRelay CA
+    #[command(subcommand)]
+    Cert(CertCommand),
+
+    /// Manage the DevRelay CA
+    #[command(subcommand)]
+    Ca(CaCommand),
+
+    /// Print shell exports that make Node, Python, curl and Go trust the DevRelay CA
+    Env {
+        /// Path to configuration file (used to locate the CA; defaults apply if it is missing)
+        #[arg(short, long, default_value = "config.yaml")]
+        config: PathBuf,
+
+        /// Shell syntax for the exports
+        #[arg(short, long, value_enum, default_value_t = Shell::Sh)]
+        shell: Shell,
+
+        /// Also import the CA into this JDK's cacerts (its JAVA_HOME)
+        #[arg(long, value_name = "JAVA_HOME")]
+        jdk: Option<PathBuf>,
+    },
+
+    /// Check that each TLS host verifies against the system trust store (devrelay must be running)
+    Verify {
+        /// Path to configuration file
+        #[arg(short, long, default_value = "config.yaml")]
+        config: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CertCommand {
+    /// Issue a certificate for any DNS names, IP addresses or wildcards
+    Issue {
+        /// Names to include in the certificate (e.g. db.test "*.kafka.test" 127.0.0.1)
+        #[arg(required = true)]
+        names: Vec<String>,
+
+        /// Path to configuration file (used to locate the CA; defaults apply if it is missing)
+        #[arg(short, long, default_value = "config.yaml")]
+        config: PathBuf,
+
+        /// Directory to write the certificate files to
+        #[arg(short, long, default_value = ".")]
+        out_dir: PathBuf,
+
+        /// Base filename for the output files (defaults to the first name)
+        #[arg(long)]
+        file_name: Option<String>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = CertFormat::Pem)]
+        format: CertFormat,
+
+        /// Append the CA certificate to the certificate file
+        #[arg(long)]
+        chain: bool,
+
+        /// Validity period in days
+        #[arg(long, default_value_t = 365)]
+        days: u32,
+
+        /// Password for the PKCS#12 bundle (prompted for if omitted)
+        #[arg(long)]
+        password: Option<String>,
+
+        /// Encrypt the PKCS#12 bundle with 3DES and SHA-1 instead of AES, for older JDKs (before
+        /// 8u301), macOS Keychain and older Windows
+        #[arg(long)]
+        legacy: bool,
+    },
+
+    /// Export the certificate DevRelay generated for a route host, e.g. as PKCS#12 for a JVM
+    /// backend or DER for a device
+    Export {
+        /// Route host (e.g. app.test or "*.apps.test")
+        host: String,
+
+        /// Path to configuration file (used to locate the routes and cert_dir)
+        #[arg(short, long, default_value = "config.yaml")]
+        config: PathBuf,
+
+        /// Directory to write the certificate files to
+        #[arg(short, long, default_value = ".")]
+        out_dir: PathBuf,
+
+        /// Base filename for the output files (defaults to the host)
+        #[arg(long)]
+        file_name: Option<String>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = CertFormat::P12)]
+        format: CertFormat,
+
+        /// Password for the PKCS#12 bundle (prompted for if omitted)
+        #[arg(long)]
+        password: Option<String>,
+
+        /// Encrypt the PKCS#12 bundle with 3DES and SHA-1 instead of AES, for older JDKs (before
+        /// 8u301), macOS Keychain and older Windows
+        #[arg(long)]
+        legacy: bool,
+    },
+
+    /// Issue a client certificate for routes with `client_auth` (mTLS)
+    Client {
+        /// Client identity, used as the certificate's common name (e.g. alice or billing-service)
+        name: String,
+
+        /// Path to configuration file (used to locate the CA; defaults apply if it is missing)
+        #[arg(short, long, default_value = "config.yaml")]
+        config: PathBuf,
+
+        /// Directory to write the certificate files to
+        #[arg(short, long, default_value = ".")]
+        out_dir: PathBuf,
+
+        /// Base filename for the output files (defaults to the name)
+        #[arg(long)]
+        file_name: Option<String>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = CertFormat::Pem)]
+        format: CertFormat,
+
+        /// Validity period in days
+        #[arg(long, default_value_t = 365)]
+        days: u32,
+
+        /// Password for the PKCS#12 bundle (prompted for if omitted)
+        #[arg(long)]
+        password: Option<String>,
+
+        /// Encrypt the PKCS#12 bundle with 3DES and SHA-1 instead of AES, for older JDKs (before
+        /// 8u301), macOS Keychain and older Windows
+        #[arg(long)]
+        legacy: bool,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum CertFormat {
+    /// Separate <name>.crt and <name>.key PEM files
+    Pem,
+    /// Single <name>.pem holding the certificate, CA and private key
+    Bundle,
+    /// Password-protected <name>.p12 with certificate, key and CA (for JVM services, Windows, macOS)
+    P12,
+    /// DER-encoded <name>.cer certificate and <name>.key.der PKCS#8 key
+    Der,
+}
+
+#[derive(Subcommand, Debug)]
+enum CaCommand {
+    /// Export the CA certificate for devices and runtimes that don't read PEM files
+    Export {
+        /// Path to configuration file (used to locate the CA; defaults apply if it is missing)
+        #[arg(short, long, default_value = "config.yaml")]
+        config: PathBuf,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = CaFormat::Pem)]
+        format: CaFormat,
+
+        /// Output file (defaults to devrelay-ca.<crt|cer|jks> in the current directory)
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+
+        /// Truststore password (JKS only)
+        #[arg(long, default_value = export::DEFAULT_JKS_PASSWORD)]
+        password: String,
+    },
+    /// Show the CA and the certificates in cert_dir, and whether the CA is trusted
+    Info {
+        /// Path to configuration file (used to locate the CA and cert_dir)
+        #[arg(short, long, default_value = "config.yaml")]
+        config: PathBuf,
+
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Replace the CA: trust a new one, re-issue leaf certs, then remove the old CA
+    Rotate {
+        /// Path to configuration file (used to locate the CA and the routes to re-issue)
+        #[arg(short, long, default_value = "config.yaml")]
+        config: PathBuf,
+
+        /// Remove the old CA without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Only remove the old CA kept by an earlier rotation
+        #[arg(long)]
+        finish: bool,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Shell {
+    /// POSIX shells (bash, zsh): `eval "$(devrelay env)"`
+    Sh,
+    /// fish: `devrelay env --shell fish | source`
+    Fish,
+    /// PowerShell: `devrelay env --shell powershell | Invoke-Expression`
+    Powershell,
+}
+
+/// Variables pointed at the combined CA bundle by `devrelay env`.
+const BUNDLE_ENV_VARS: &[&str] = &[
+    "NODE_EXTRA_CA_CERTS",
+    "REQUESTS_CA_BUNDLE",
+    "SSL_CERT_FILE",
+    "CURL_CA_BUNDLE",
+];
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum CaFormat {
+    /// PEM certificate
+    Pem,
+    /// DER certificate (.cer) for installing on Android and iOS devices and simulators
+    Der,
+    /// Java KeyStore truststore containing the CA
+    Jks,
 }
 
 fn main() -> Result<()> {
@@ -50,22 +308,145 @@ fn main() -> Result<()> {
             skip_install,
             force_install,
             uninstall,
+            remove_ca,
+            install_root,
+            lan,
+            quiet,
+        } => run_server(
+            config,
+            skip_install,
+            force_install,
+            uninstall,
+            remove_ca,
+            install_root,
+            lan,
             quiet,
-        } => run_server(config, skip_install, force_install, uninstall, quiet)?,
+        )?,
+        Command::Cert(CertCommand::Issue {
+            names,
+            config,
+            out_dir,
+            file_name,
+            format,
+            chain,
+            days,
+            password,
+            legacy,
+        }) => run_cert_issue(
+            config,
+            &names,
+            &out_dir,
+            file_name,
+            format,
+            CertIssueOptions {
+                chain,
+                days,
+                password,
+                legacy,
+            },
+        )?,
+        Command::Cert(CertCommand::Export {
+            host,
+            config,
+            out_dir,
+            file_name,
+            format,
+            password,
+            legacy,
+        }) => run_cert_export(config, &host, &out_dir, file_name, format, password, legacy)?,
+        Command::Cert(CertCommand::Client {
+            name,
+            config,
+            out_dir,
+            file_name,
+            format,
+            days,
+            password,
+            legacy,
+        }) => run_cert_client(
+            config,
+            &name,
+            &out_dir,
+            file_name,
+            format,
+            CertIssueOptions {
+                chain: false,
+                days,
+                password,
+                legacy,
+            },
+        )?,
+        Command::Ca(CaCommand::Export {
+            config,
+            format,
+            out,
+            password,
+        }) => run_ca_export(config, format, out, &password)?,
+        Command::Env { config, shell, jdk } => run_env(config, shell, jdk)?,
+        Command::Verify { config } => run_verify(config)?,
+        Command::Ca(CaCommand::Info { config, json }) => run_ca_info(config, json)?,
+        Command::Ca(CaCommand::Rotate {
+            config,
+            yes,
+            finish,
+        }) => run_ca_rotate(config, yes, finish)?,
     }
 
     Ok(())
 }
 
-fn run_server(
-    config_arg: PathBuf,
-    skip_install: bool,
-    force_install: bool,
-    uninstall: bool,
-    quiet: bool,
-) -> Result<()> {
+/// Create the certificate manager described by the `tls` section of the config.
+fn build_cert_manager(config: &Config) -> Result<CertManager> {
+    let mut cert_manager = CertManager::new(&config.tls.cert_dir, config.tls.ca_name.clone())
+        .with_ca_dir(config.tls.ca_dir())
+        .with_name_constraints(config.name_constraint_suffixes()?)
+        .with_encrypted_ca_key(config.tls.encrypt_ca_key)
+        .with_leaf_lifetime(config.tls.leaf_lifetime()?);
+    if let Some((ca_cert, ca_key)) = config.tls.external_ca()? {
+        cert_manager = cert_manager.with_external_ca(ca_cert, ca_key);
+    }
+    Ok(cert_manager)
+}
+
+/// Installer for this machine, or for the file tree under `install_root` when one is given.
+fn build_installer(config: &Config, install_root: Option<&Path>) -> Installer {
+    let installer = match install_root {
+        Some(root) => Installer::new(FsTrustStore::new(root), FsHostsFile::new(root)),
+        None => Installer::system(&config.tls.ca_name),
+    };
+    // The project's hosts entries are marked with its cert_dir, which is unique per project
+    let cert_dir = Path::new(&config.tls.cert_dir);
+    let project = std::path::absolute(cert_dir).unwrap_or_else(|_| cert_dir.to_path_buf());
+    installer.with_project(project.display().to_string())
+}
+
+/// Hosts entries pointing every host name at the address it is served on.
+fn host_entries(config: &Config) -> Vec<HostEntry> {
+    config
+        .host_names()
+        .into_iter()
+        .map(|name| HostEntry {
+            addresses: config.addresses_for_host(&name),
+            name,
+        })
+        .collect()
+}
+
+/// TLS endpoints with the addresses each is served on.
+fn tls_endpoint_addresses(config: &Config) -> Vec<(String, u16, Vec<IpAddr>)> {
+    config
+        .tls_endpoints()
+        .into_iter()
+        .map(|(host, port)| {
+            let ips = config.addresses_for_host(&host);
+            (host, port, ips)
+        })
+        .collect()
+}
+
+fn resolve_config_path(config_arg: PathBuf) -> PathBuf {
     // Resolve config path - prefer current working directory (project root) for bare filenames
-    let config_path = if config_arg.is_absolute() {
+    if config_arg.is_absolute() {
         config_arg
     } else if config_arg.starts_with(".") || config_arg.starts_with("..") {
         // Explicitly relative path (./foo or ../foo) - resolve from CWD
@@ -76,76 +457,858 @@ fn run_server(
         std::env::current_dir()
             .map(|cwd| cwd.join(&config_arg))
             .unwrap_or(config_arg)
+    }
+}
+
+/// Load the config for subcommands that only need the CA; defaults apply when the file is missing.
+fn load_config_or_default(config_arg: PathBuf) -> Result<Config> {
+    let config_path = resolve_config_path(config_arg);
+    if config_path.exists() {
+        Config::load(&config_path).with_context(|| "Failed to load configuration")
+    } else {
+        Ok(Config::default())
+    }
+}
+
+/// Human-readable duration, e.g. "4h", "40m" or "90s".
+fn format_duration(duration: std::time::Duration) -> String {
+    let secs = duration.as_secs();
+    if secs.is_multiple_of(3600) {
+        format!("{}h", secs / 3600)
+    } else if secs.is_multiple_of(60) {
+        format!("{}m", secs / 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// Names covered by the combined certificate served on TLS listeners: the `listen_tls` route
+/// hosts, the ACME host and `tls.extra_sans` (empty when nothing listens with TLS).
+fn listener_cert_names(config: &Config) -> Vec<String> {
+    let mut names: Vec<String> = config
+        .routes
+        .iter()
+        .filter(|r| r.listen_tls)
+        .map(|r| r.host.clone())
+        .collect();
+    if let Some(acme) = &config.acme {
+        names.push(acme.host.clone());
+    }
+    if names.is_empty() {
+        return names;
+    }
+    for name in &config.tls.extra_sans {
+        if !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+    names
+}
+
+/// Ask a yes/no question on the terminal; defaults to no (and to no when stdin is not a terminal).
+fn confirm(question: &str) -> Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    print!("{} [y/N] ", question);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .context("Failed to read answer")?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// File stem for a certificate name (e.g. "*.kafka.test" -> "_wildcard.kafka.test").
+fn cert_file_stem(name: &str) -> String {
+    name.replace('*', "_wildcard").replace(':', "_")
+}
+
+struct CertIssueOptions {
+    chain: bool,
+    days: u32,
+    password: Option<String>,
+    legacy: bool,
+}
+
+/// Password for an exported bundle: the `--password` value, or prompted for interactively.
+fn export_password(password: Option<String>) -> Result<String> {
+    match password {
+        Some(p) => Ok(p),
+        None => {
+            let password = rpassword::prompt_password("🔑 Export password: ")
+                .context("Failed to read password (use --password)")?;
+            let again = rpassword::prompt_password("   Confirm password: ")
+                .context("Failed to read password (use --password)")?;
+            if again != password {
+                anyhow::bail!("Passwords do not match");
+            }
+            Ok(password)
+        }
+    }
+}
+
+fn run_cert_issue(
+    config_arg: PathBuf,
+    names: &[String],
+    out_dir: &Path,
+    file_name: Option<String>,
+    format: CertFormat,
+    options: CertIssueOptions,
+) -> Result<()> {
+    let CertIssueOptions {
+        chain,
+        days,
+        password,
+        legacy,
+    } = options;
+
+    let config = load_config_or_default(config_arg)?;
+    let cert_manager = build_cert_manager(&config)?;
+    cert_manager.init()?;
+
+    for name in names {
+        if !cert_manager.ca_permits(name)? {
+            println!(
+                "⚠️  {} is outside the CA's name constraints; clients will reject this certificate.",
+                name
+            );
+        }
+    }
+
+    let (mut cert_pem, key_pem) = cert_manager.issue_cert(names, i64::from(days))?;
+    if chain || matches!(format, CertFormat::Bundle) {
+        cert_pem.push_str(&cert_manager.ca_cert_pem()?);
+    }
+
+    let stem = file_name.unwrap_or_else(|| cert_file_stem(&names[0]));
+    println!("\n✓ Certificate issued for: {}", names.join(", "));
+    write_cert_files(
+        &cert_manager,
+        out_dir,
+        &stem,
+        &names[0],
+        format,
+        cert_pem,
+        key_pem,
+        password,
+        legacy,
+    )
+}
+
+fn run_cert_export(
+    config_arg: PathBuf,
+    host: &str,
+    out_dir: &Path,
+    file_name: Option<String>,
+    format: CertFormat,
+    password: Option<String>,
+    legacy: bool,
+) -> Result<()> {
+    let config = load_config_or_default(config_arg)?;
+    let Some(route) = config
+        .routes
+        .iter()
+        .find(|route| route.host.eq_ignore_ascii_case(host))
+    else {
+        anyhow::bail!(
+            "No route for {}; use `devrelay cert issue` for other names",
+            host
+        );
+    };
+    let cert_manager = build_cert_manager(&config)?;
+    cert_manager.init()?;
+    // Same as on startup: reused while valid, re-issued when missing or due for renewal
+    cert_manager.generate_server_cert(&route.host)?;
+
+    let read = |path: PathBuf| {
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))
+    };
+    let mut cert_pem = read(cert_manager.server_cert_path(&route.host))?;
+    let key_pem = read(cert_manager.server_key_path(&route.host))?;
+    if matches!(format, CertFormat::Bundle) {
+        cert_pem.push_str(&cert_manager.ca_cert_pem()?);
+    }
+
+    let stem = file_name.unwrap_or_else(|| cert_file_stem(&route.host));
+    println!("✓ Exporting the certificate for route: {}", route.host);
+    write_cert_files(
+        &cert_manager,
+        out_dir,
+        &stem,
+        &route.host,
+        format,
+        cert_pem,
+        key_pem,
+        password,
+        legacy,
+    )
+}
+
+fn run_cert_client(
+    config_arg: PathBuf,
+    name: &str,
+    out_dir: &Path,
+    file_name: Option<String>,
+    format: CertFormat,
+    options: CertIssueOptions,
+) -> Result<()> {
+    let config = load_config_or_default(config_arg)?;
+    let cert_manager = build_cert_manager(&config)?;
+    cert_manager.init()?;
+
+    // OpenSSL checks hostname-like common names against the CA's name constraints
+    if name.contains('.') && !cert_manager.ca_permits(name)? {
+        println!(
+            "⚠️  {} is outside the CA's name constraints; use a name without dots (e.g. alice).",
+            name
+        );
+    }
+
+    let (mut cert_pem, key_pem) = cert_manager.issue_client_cert(name, i64::from(options.days))?;
+    if matches!(format, CertFormat::Bundle) {
+        cert_pem.push_str(&cert_manager.ca_cert_pem()?);
+    }
+
+    let stem = file_name.unwrap_or_else(|| format!("{}-client", cert_file_stem(name)));
+    println!("\n✓ Client certificate issued for: {}", name);
+    write_cert_files(
+        &cert_manager,
+        out_dir,
+        &stem,
+        name,
+        format,
+        cert_pem,
+        key_pem,
+        options.password,
+        options.legacy,
+    )
+}
+
+/// Write an issued certificate and key to `out_dir` in the requested format.
+#[allow(clippy::too_many_arguments)]
+fn write_cert_files(
+    cert_manager: &CertManager,
+    out_dir: &Path,
+    stem: &str,
+    friendly_name: &str,
+    format: CertFormat,
+    cert_pem: String,
+    key_pem: String,
+    password: Option<String>,
+    legacy: bool,
+) -> Result<()> {
+    fs::create_dir_all(out_dir)
+        .with_context(|| format!("Failed to create output directory: {}", out_dir.display()))?;
+
+    match format {
+        CertFormat::Pem => {
+            let cert_path = out_dir.join(format!("{}.crt", stem));
+            let key_path = out_dir.join(format!("{}.key", stem));
+            fs::write(&cert_path, cert_pem).context("Failed to write certificate")?;
+            certs::write_private_key(&key_path, key_pem).context("Failed to write private key")?;
+            println!("  Certificate: {}", cert_path.display());
+            println!("  Private key: {}", key_path.display());
+        }
+        CertFormat::Bundle => {
+            let bundle_path = out_dir.join(format!("{}.pem", stem));
+            certs::write_private_key(&bundle_path, cert_pem + &key_pem)
+                .context("Failed to write certificate bundle")?;
+            println!("  Bundle: {}", bundle_path.display());
+        }
+        CertFormat::P12 => {
+            let password = export_password(password)?;
+            let p12 = export::pkcs12(
+                friendly_name,
+                &cert_pem,
+                &key_pem,
+                &cert_manager.ca_cert_pem()?,
+                &password,
+                legacy,
+            )?;
+            let p12_path = out_dir.join(format!("{}.p12", stem));
+            certs::write_private_key(&p12_path, p12).context("Failed to write PKCS#12 bundle")?;
+            println!("  PKCS#12 bundle: {}", p12_path.display());
+        }
+        CertFormat::Der => {
+            let cert_path = out_dir.join(format!("{}.cer", stem));
+            let key_path = out_dir.join(format!("{}.key.der", stem));
+            fs::write(&cert_path, export::pem_to_der(&cert_pem)?)
+                .context("Failed to write certificate")?;
+            certs::write_private_key(&key_path, export::key_pem_to_der(&key_pem)?)
+                .context("Failed to write private key")?;
+            println!("  Certificate: {}", cert_path.display());
+            println!("  Private key: {}", key_path.display());
+        }
+    }
+    println!(
+        "  CA certificate: {}",
+        cert_manager.ca_cert_path().display()
+    );
+
+    Ok(())
+}
+
+fn run_ca_export(
+    config_arg: PathBuf,
+    format: CaFormat,
+    out: Option<PathBuf>,
+    password: &str,
+) -> Result<()> {
+    let config = load_config_or_default(config_arg)?;
+    let cert_manager = build_cert_manager(&config)?;
+    cert_manager.init()?;
+
+    let ca_pem = cert_manager.ca_cert_pem()?;
+    let (contents, extension) = match format {
+        CaFormat::Pem => (ca_pem.into_bytes(), "crt"),
+        CaFormat::Der => (export::pem_to_der(&ca_pem)?, "cer"),
+        CaFormat::Jks => (
+            export::jks_truststore(&[("devrelay-ca".to_string(), ca_pem)], password)?,
+            "jks",
+        ),
     };
 
+    let out = out.unwrap_or_else(|| PathBuf::from(format!("devrelay-ca.{}", extension)));
+    fs::write(&out, contents).with_context(|| format!("Failed to write {}", out.display()))?;
+
+    println!("✓ CA certificate exported to: {}", out.display());
+    if matches!(format, CaFormat::Jks) {
+        println!(
+            "  Use with: -Djavax.net.ssl.trustStore={} -Djavax.net.ssl.trustStorePassword={}",
+            out.display(),
+            password
+        );
+    }
+
+    Ok(())
+}
+
+fn run_ca_info(config_arg: PathBuf, json: bool) -> Result<()> {
+    let config = load_config_or_default(config_arg)?;
+    let cert_manager = build_cert_manager(&config)?;
+
+    let ca_cert_path = cert_manager.ca_cert_path();
+    let ca = if ca_cert_path.exists() {
+        Some(
+            X509::from_pem(&fs::read(&ca_cert_path).context("Failed to read CA certificate")?)
+                .context("Failed to parse CA certificate")?,
+        )
+    } else {
+        None
+    };
+
+    let installer = Installer::system(&config.tls.ca_name);
+    let mut ca_certs = Vec::new();
+    for path in [ca_cert_path.clone(), cert_manager.old_ca_cert_path()] {
+        if path.exists() {
+            let mut info = inspect::inspect(&path, ca.as_ref())?;
+            info.trusted = Some(installer.is_ca_installed(&path)?);
+            ca_certs.push(info);
+        }
+    }
+
+    let mut certs = Vec::new();
+    for path in cert_manager.cert_files()? {
+        match inspect::inspect(&path, ca.as_ref()) {
+            Ok(info) => certs.push(info),
+            Err(e) => eprintln!("⚠️  Skipping {}: {:#}", path.display(), e),
+        }
+    }
+
+    if json {
+        let report = inspect::Report {
+            ca: ca_certs,
+            certificates: certs,
+        };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    if ca_certs.is_empty() {
+        println!(
+            "No CA at {} yet; it is generated on first start.",
+            ca_cert_path.display()
+        );
+    }
+    for info in &ca_certs {
+        let label = if info.path == ca_cert_path {
+            "CA"
+        } else {
+            "Previous CA (from `ca rotate`)"
+        };
+        print_cert_info(label, info);
+    }
+    if certs.is_empty() {
+        println!("No certificates in {}", config.tls.cert_dir);
+    }
+    for info in &certs {
+        print_cert_info("Certificate", info);
+    }
+
+    Ok(())
+}
+
+fn print_cert_info(label: &str, info: &inspect::CertInfo) {
+    println!("{}: {}", label, info.path.display());
+    println!("  Subject:     {}", info.subject);
+    if !info.sans.is_empty() {
+        println!("  Names:       {}", info.sans.join(", "));
+    }
+    println!("  Issuer:      {}", info.issuer);
+    println!(
+        "  Valid:       {} to {}{}",
+        info.not_before,
+        info.not_after,
+        if info.expired { " ❌ expired" } else { "" }
+    );
+    println!("  SHA-256:     {}", info.fingerprint_sha256);
+    println!("  Key:         {}", info.key_type);
+    if !info.is_ca || !info.chains_to_ca {
+        println!(
+            "  Current CA:  {}",
+            if info.chains_to_ca {
+                "✅ issued by it"
+            } else {
+                "❌ not issued by it"
+            }
+        );
+    }
+    if let Some(trusted) = info.trusted {
+        println!(
+            "  Trusted:     {}",
+            if trusted {
+                "✅ in the system trust store"
+            } else {
+                "❌ not in the system trust store (run `devrelay start --force-install`)"
+            }
+        );
+    }
+    println!();
+}
+
+fn run_ca_rotate(config_arg: PathBuf, yes: bool, finish: bool) -> Result<()> {
+    let config = load_config_or_default(config_arg)?;
+    let cert_manager = build_cert_manager(&config)?;
+    cert_manager.init()?;
+    let installer = Installer::system(&config.tls.ca_name);
+
+    if !finish {
+        cert_manager.rotate_ca()?;
+        let ca_cert_path = cert_manager.ca_cert_path();
+        println!(
+            "✓ New CA certificate generated at: {}\n",
+            ca_cert_path.display()
+        );
+
+        // Trust the new CA next to the old one, so existing certs keep working meanwhile
+        if !installer.is_ca_installed(&ca_cert_path)?
+            && !installer.install_ca_cert(&ca_cert_path)?
+        {
+            println!(
+                "\n⚠️  The new CA is not trusted yet; the old CA is kept. Trust {} and run `devrelay ca rotate --finish`.",
+                ca_cert_path.display()
+            );
+            return Ok(());
+        }
+
+        println!("\nRe-issuing certificates...");
+        for route in &config.routes {
+            cert_manager.generate_server_cert(&route.host)?;
+        }
+        let listener_names = listener_cert_names(&config);
+        if !listener_names.is_empty() {
+            cert_manager.generate_combined_server_cert(&listener_names)?;
+        }
+        refresh_trust_bundle(&cert_manager)?;
+        println!(
+            "ℹ️  Restart running proxies to serve the new certificates. Other projects sharing this CA re-issue theirs on start.\n"
+        );
+    }
+
+    let old_ca_path = cert_manager.old_ca_cert_path();
+    if !old_ca_path.exists() {
+        println!("✅ No previous CA to remove");
+        return Ok(());
+    }
+
+    if yes
+        || confirm(
+            "Remove the old CA from the trust store? Certificates it issued will stop being trusted.",
+        )?
+    {
+        if installer.uninstall_ca_cert(&old_ca_path)? {
+            cert_manager.remove_old_ca()?;
+            refresh_trust_bundle(&cert_manager)?;
+            println!("✓ Old CA removed");
+        }
+    } else {
+        println!(
+            "Old CA kept at {} and still trusted. Run `devrelay ca rotate --finish` to remove it.",
+            old_ca_path.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Rewrite the bundle from `devrelay env` after the CA changed, if one was written before.
+fn refresh_trust_bundle(cert_manager: &CertManager) -> Result<()> {
+    if cert_manager.trust_bundle_path().exists() {
+        cert_manager.write_trust_bundle(openssl_probe::probe().cert_file.as_deref())?;
+    }
+    Ok(())
+}
+
+fn run_env(config_arg: PathBuf, shell: Shell, jdk: Option<PathBuf>) -> Result<()> {
+    let config = load_config_or_default(config_arg)?;
+    let cert_manager = build_cert_manager(&config)?;
+    if !cert_manager.ca_cert_path().exists() {
+        anyhow::bail!(
+            "No CA at {} yet; run `devrelay start` first",
+            cert_manager.ca_cert_path().display()
+        );
+    }
+
+    let system_bundle = openssl_probe::probe().cert_file;
+    if system_bundle.is_none() {
+        eprintln!("⚠️  No system CA bundle found; the bundle only contains the DevRelay CA.");
+    }
+    let bundle = cert_manager
+        .write_trust_bundle(system_bundle.as_deref())?
+        .canonicalize()
+        .context("Failed to resolve CA bundle path")?;
+
+    if let Some(java_home) = jdk {
+        Installer::install_ca_cert_jdk(&cert_manager.ca_cert_path(), &java_home)?;
+    }
+
+    // Single-quoted for each shell's own escaping rules
+    let path = bundle.to_string_lossy();
+    for name in BUNDLE_ENV_VARS {
+        match shell {
+            Shell::Sh => println!("export {}='{}'", name, path.replace('\'', "'\\''")),
+            Shell::Fish => println!(
+                "set -gx {} '{}';",
+                name,
+                path.replace('\\', "\\\\").replace('\'', "\\'")
+            ),
+            Shell::Powershell => println!("$env:{} = '{}'", name, path.replace('\'', "''")),
+        }
+    }
+    if std::io::stdout().is_terminal() {
+        eprintln!("\n# Apply with: eval \"$(devrelay env)\"");
+    }
+
+    Ok(())
+}
+
+fn run_verify(config_arg: PathBuf) -> Result<()> {
+    let config_path = resolve_config_path(config_arg);
+    let config = Config::load(&config_path).with_context(|| "Failed to load configuration")?;
+
+    let endpoints = tls_endpoint_addresses(&config);
+    if endpoints.is_empty() {
+        println!("No TLS hosts configured (set tls.enabled and listen_tls on a route).");
+        return Ok(());
+    }
+
+    println!("🔍 Verifying TLS against the system trust store...");
+    let connector = verify::system_trust_connector()?;
+    let checks = check_endpoints(&connector, &endpoints);
+    if !verify::print_checks(&checks) {
+        anyhow::bail!("TLS verification failed for some hosts");
+    }
+    Ok(())
+}
+
+/// URLs (and QR codes) other devices reach each port through in `--lan` mode. Requests to the
+/// LAN address carry no route host, so each port serves its first route there.
+fn print_lan_urls(
+    config: &Config,
+    lan_address: &lan::LanAddress,
+    listen_addrs: &[proxy::ListenAddr],
+) -> Result<()> {
+    println!("\n📱 On the LAN ({}):", lan_address.interface);
+    for listen_addr in listen_addrs.iter().filter(|l| l.ip == lan_address.ip) {
+        let Some(route) = config.lan_route(listen_addr.port) else {
+            continue;
+        };
+        let url = lan::url(lan_address.ip, listen_addr.port, listen_addr.tls);
+        println!("  {} -> {}", url, route.host);
+        println!("{}", lan::qr_code(&url)?);
+        // Announced over mDNS, so reachable by name too
+        for host in config.mdns_hosts() {
+            if config
+                .get_route_by_host(&host)
+                .is_some_and(|r| r.port == listen_addr.port)
+            {
+                let url = lan::host_url(&host, listen_addr.port, listen_addr.tls);
+                println!("  {}", url);
+                println!("{}", lan::qr_code(&url)?);
+            }
+        }
+    }
+    if listen_addrs.iter().any(|l| l.ip == lan_address.ip && l.tls) {
+        println!(
+            "  Devices must trust the DevRelay CA for HTTPS: `devrelay ca export --format der` and install the .cer on the device."
+        );
+    }
+    println!();
+    Ok(())
+}
+
+/// Check every TLS endpoint over each of its addresses, so a host that only works over one
+/// address family shows up.
+fn check_endpoints(
+    connector: &openssl::ssl::SslConnector,
+    endpoints: &[(String, u16, Vec<IpAddr>)],
+) -> Vec<verify::HostCheck> {
+    endpoints
+        .iter()
+        .flat_map(|(host, port, ips)| {
+            ips.iter()
+                .map(move |ip| verify::check_host(connector, host, *ip, *port))
+        })
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_server(
+    config_arg: PathBuf,
+    skip_install: bool,
+    force_install: bool,
+    uninstall: bool,
+    remove_ca: bool,
+    install_root: Option<PathBuf>,
+    lan: Option<Option<String>>,
+    quiet: bool,
+) -> Result<()> {
+    let config_path = resolve_config_path(config_arg);
+
     println!("DevRelay - Local Development Proxy");
     println!("==================================\n");
 
     // Load configuration
     println!("Loading config from: {}", config_path.display());
-    let config = Config::load(&config_path).with_context(|| "Failed to load configuration")?;
+    let mut config = Config::load(&config_path).with_context(|| "Failed to load configuration")?;
 
     println!("Loaded {} route(s)\n", config.routes.len());
 
+    // LAN mode: other devices connect to the interface address, so certificates must cover it
+    let lan_address = match &lan {
+        Some(interface) if !uninstall => {
+            let lan_address = lan::lan_address(interface.as_deref())?;
+            println!(
+                "📡 LAN mode: also listening on {} ({})\n",
+                lan_address.ip, lan_address.interface
+            );
+            let ip = lan_address.ip.to_string();
+            if !config.tls.extra_sans.contains(&ip) {
+                config.tls.extra_sans.push(ip);
+            }
+            Some(lan_address)
+        }
+        _ => None,
+    };
+
     // Handle uninstall
     if uninstall {
-        let domains: Vec<String> = config.routes.iter().map(|r| r.host.clone()).collect();
-        Installer::run_uninstall(&config.tls.ca_name, &domains)?;
+        let domains = config.host_names();
+        let installer = build_installer(&config, install_root.as_deref());
+        if config.tls.external_ca()?.is_some() {
+            // An imported CA (e.g. mkcert's) is managed by its own tool, so leave it trusted
+            if remove_ca {
+                println!(
+                    "⚠️  The CA is external (tls.ca_cert); remove it with the tool that created it"
+                );
+            }
+            installer.uninstall_hosts_entries(&domains)?;
+        } else if remove_ca {
+            let cert_manager = build_cert_manager(&config)?;
+            println!(
+                "⚠️  Removing the CA from the trust store. Every project using the CA in {} \
+                 loses HTTPS trust until its next start.",
+                config.tls.ca_dir().display()
+            );
+            installer.run_uninstall(Some(&cert_manager.ca_cert_path()), &domains)?;
+        } else {
+            installer.run_uninstall(None, &domains)?;
+            println!(
+                "ℹ️  The CA stays trusted, since other projects may use it. Pass --remove-ca to \
+                 remove it as well."
+            );
+        }
+        if let Some(dns_config) = &config.dns
+            && dns_config.systemd_resolved
+            && install_root.is_none()
+        {
+            dns::unregister_systemd_resolved(dns_config.listen_addr())?;
+        }
         return Ok(());
     }
 
+    // Bind the DNS server early, so a taken port is reported before anything is installed
+    let dns_server = match &config.dns {
+        Some(dns_config) => {
+            anyhow::ensure!(
+                dns_config.address.is_loopback(),
+                "dns.address must be a loopback address, got {}",
+                dns_config.address
+            );
+            if dns_config.systemd_resolved {
+                dns_config.split_dns_domains()?;
+            }
+            let public: Vec<String> = config
+                .host_names()
+                .into_iter()
+                .filter(|host| host.parse::<IpAddr>().is_err() && !config::is_reserved_domain(host))
+                .collect();
+            if !public.is_empty() && dns_config.domains.is_empty() {
+                println!(
+                    "⚠️  Hosts under public top-level domains ({}) resolve through the DNS \
+                     server only when it is queried directly. Prefer a reserved domain such as .test.",
+                    public.join(", ")
+                );
+            }
+            let hosts = host_entries(&config)
+                .into_iter()
+                .map(|entry| (entry.name, entry.addresses))
+                .collect();
+            let zone = dns::Zone::new(hosts, config.dns_domains());
+            Some(dns::DnsServer::bind(
+                dns_config.listen_addr(),
+                zone,
+                dns_config.upstream_addr()?,
+            )?)
+        }
+        None => {
+            if config.routes.iter().any(|r| r.host.starts_with("*.")) {
+                println!(
+                    "⚠️  Wildcard hosts cannot be added to /etc/hosts; enable the built-in DNS server (`dns:` in the config) to resolve them.\n"
+                );
+            }
+            None
+        }
+    };
+
+    // `.local` hosts are announced to the LAN over multicast DNS
+    let mdns_hosts = config.mdns_hosts();
+    let mdns_responder = match &lan_address {
+        Some(lan_address) if !mdns_hosts.is_empty() => {
+            Some(mdns::MdnsResponder::bind(mdns_hosts.clone(), lan_address)?)
+        }
+        None if !mdns_hosts.is_empty() => {
+            println!(
+                "ℹ️  .local hosts are announced over mDNS in --lan mode only; other devices can't resolve them now.\n"
+            );
+            None
+        }
+        _ => None,
+    };
+
     // Initialize certificate manager and generate certificates
-    let mut tls_cert_key: Option<(String, String)> = None;
+    // Combined listener cert, and the CA cert client certificates are verified against
+    let mut listener_tls: Option<(Arc<ListenerCert>, String)> = None;
+    let mut acme_cert_manager: Option<Arc<CertManager>> = None;
+    let mut cert_rotation: Option<CertRotation> = None;
 
     if config.tls.enabled {
-        let cert_manager = CertManager::new(&config.tls.cert_dir, config.tls.ca_name.clone());
+        let cert_manager = Arc::new(build_cert_manager(&config)?);
         cert_manager.init()?;
 
         // Generate server certificates for all configured hosts
         for route in &config.routes {
+            if !cert_manager.ca_permits(&route.host)? {
+                println!(
+                    "⚠️  {} is outside the CA's name constraints; browsers will reject its certificate.",
+                    route.host
+                );
+            }
             cert_manager.generate_server_cert(&route.host)?;
         }
 
-        // Generate combined cert for TLS listeners (covers all listen_tls domains)
-        let tls_domains: Vec<String> = config
-            .routes
-            .iter()
-            .filter(|r| r.listen_tls)
-            .map(|r| r.host.clone())
-            .collect();
+        if let Some(acme) = &config.acme {
+            if !cert_manager.ca_permits(&acme.host)? {
+                println!(
+                    "⚠️  ACME host {} is outside the CA's name constraints; clients will reject it.",
+                    acme.host
+                );
+            }
+            acme_cert_manager = Some(cert_manager.clone());
+        }
 
+        // Generate combined cert for TLS listeners (covers all listen_tls domains)
+        let tls_domains = listener_cert_names(&config);
         if !tls_domains.is_empty() {
             cert_manager.generate_combined_server_cert(&tls_domains)?;
-            tls_cert_key = Some((
-                cert_manager
-                    .combined_cert_path()
-                    .to_string_lossy()
-                    .into_owned(),
-                cert_manager
-                    .combined_key_path()
-                    .to_string_lossy()
-                    .into_owned(),
+            let listener_cert = ListenerCert::load(
+                cert_manager.combined_cert_path(),
+                cert_manager.combined_key_path(),
+            )?;
+            listener_tls = Some((
+                Arc::new(listener_cert),
+                cert_manager.ca_cert_path().to_string_lossy().into_owned(),
             ));
         }
 
+        if let Some(interval) = cert_manager.leaf_rotation_interval() {
+            println!(
+                "🔄 Short-lived certificates: rotating every {}",
+                format_duration(interval)
+            );
+            cert_rotation = Some(CertRotation {
+                cert_manager: cert_manager.clone(),
+                hosts: config.routes.iter().map(|r| r.host.clone()).collect(),
+                listener_names: tls_domains,
+                listener_cert: listener_tls.as_ref().map(|(cert, _)| cert.clone()),
+                interval,
+            });
+        }
+
         println!();
 
         // Auto-install CA cert and /etc/hosts entries if needed
         if !skip_install {
             let ca_cert_path = cert_manager.ca_cert_path();
-            let ca_name = &config.tls.ca_name;
-            let domains: Vec<String> = config.routes.iter().map(|r| r.host.clone()).collect();
-
-            let needs_install =
-                force_install || !Installer::is_ca_installed(&ca_cert_path, ca_name)?;
+            let installer = build_installer(&config, install_root.as_deref());
+            // With the built-in DNS server the hosts file is left alone
+            let domains = if config.dns.is_some() {
+                Vec::new()
+            } else {
+                host_entries(&config)
+            };
 
-            if needs_install {
-                Installer::run_install(&ca_cert_path, ca_name, &domains)?;
+            if cert_manager.is_external_ca() {
+                // Imported CAs (e.g. mkcert's) are trusted by the tool that created them
+                if !domains.is_empty() {
+                    println!("🌐 Checking /etc/hosts entries...");
+                }
+                installer.install_hosts_entries(&domains)?;
+            } else if force_install || !installer.is_ca_installed(&ca_cert_path)? {
+                installer.run_install(&ca_cert_path, &domains)?;
             } else {
                 // Still check hosts entries even if CA is installed
                 println!("✅ CA certificate already installed\n");
-                println!("🌐 Checking /etc/hosts entries...");
-                Installer::install_hosts_entries(&domains)?;
+                if !domains.is_empty() {
+                    println!("🌐 Checking /etc/hosts entries...");
+                }
+                installer.install_hosts_entries(&domains)?;
+            }
+
+            if let Some(dns_config) = &config.dns
+                && dns_config.systemd_resolved
+                && install_root.is_none()
+            {
+                if std::env::consts::OS == "linux" {
+                    println!();
+                    dns::register_systemd_resolved(
+                        dns_config.listen_addr(),
+                        &dns_config.split_dns_domains()?,
+                    )?;
+                } else {
+                    println!("⚠️  dns.systemd_resolved only applies on Linux");
+                }
             }
 
             println!();
@@ -179,17 +1342,61 @@ fn run_server(
     let mut server = pingora_core::server::Server::new(None).context("Failed to create server")?;
     server.bootstrap();
 
+    if config.acme.is_some() && !config.tls.enabled {
+        println!("⚠️  The ACME server needs tls.enabled; it is not started.\n");
+    }
+
     let config_arc = Arc::new(config);
-    let proxy = DevRelayProxy::new(config_arc.clone(), quiet);
+    let acme_server = match (&config_arc.acme, acme_cert_manager) {
+        (Some(acme), Some(cert_manager)) => {
+            let server = AcmeServer::new(config_arc.clone(), acme, cert_manager)?;
+            let port = if acme.port == 443 {
+                String::new()
+            } else {
+                format!(":{}", acme.port)
+            };
+            println!("ACME directory: https://{}{}/directory\n", acme.host, port);
+            Some(Arc::new(server))
+        }
+        _ => None,
+    };
+    let proxy = DevRelayProxy::new(config_arc.clone(), quiet, acme_server)?
+        .with_lan_address(lan_address.as_ref().map(|lan| lan.ip));
 
     let mut proxy_service = pingora_proxy::http_proxy_service(&server.configuration, proxy);
 
     // Add listeners for all configured ports (TLS or TCP)
-    let listen_addrs = get_listen_addresses(&config_arc);
+    let mut listen_addrs =
+        get_listen_addresses(&config_arc, lan_address.as_ref().map(|lan| lan.ip));
+
+    for listener in &config_arc.listeners {
+        if !listen_addrs
+            .iter()
+            .any(|l| l.port == listener.port && l.tls)
+        {
+            println!(
+                "⚠️  TLS policy for port {} is ignored: no route listens with TLS on that port.",
+                listener.port
+            );
+        }
+    }
 
     // Pre-check that ports are not already in use (Pingora would panic on bind otherwise)
+    let ipv4_enabled = config_arc.address_families().contains(&AddressFamily::Ipv4);
+    let mut ipv6_unavailable = false;
     for listen_addr in &listen_addrs {
-        if let Err(e) = std::net::TcpListener::bind(&listen_addr.addr) {
+        if let Err(e) = listen_addr.try_bind() {
+            if listen_addr.ip == AddressFamily::Ipv6.loopback()
+                && ipv4_enabled
+                && e.kind() != std::io::ErrorKind::AddrInUse
+            {
+                // IPv6 disabled on this machine; IPv4 alone still works
+                if !ipv6_unavailable {
+                    println!("⚠️  Not listening on IPv6: {}", e);
+                    ipv6_unavailable = true;
+                }
+                continue;
+            }
             if e.kind() == std::io::ErrorKind::AddrInUse {
                 let port = listen_addr.addr.rsplit(':').next().unwrap_or("?");
                 anyhow::bail!(
@@ -198,32 +1405,130 @@ fn run_server(
                     port
                 );
             }
+            if e.kind() == std::io::ErrorKind::AddrNotAvailable {
+                anyhow::bail!(
+                    "Cannot bind to {}: the address is not configured on this machine.\n\n  On macOS, add it as a loopback alias first:\n    sudo ifconfig lo0 {} {} up",
+                    listen_addr.addr,
+                    if listen_addr.ip.is_ipv6() {
+                        "inet6 alias"
+                    } else {
+                        "alias"
+                    },
+                    listen_addr.ip
+                );
+            }
             return Err(e).context(format!("Cannot bind to {}", listen_addr.addr));
         }
     }
+    if ipv6_unavailable {
+        listen_addrs.retain(|l| l.ip != AddressFamily::Ipv6.loopback());
+    }
 
     for listen_addr in &listen_addrs {
+        println!(
+            "Listening on: {} ({})",
+            listen_addr.addr,
+            if listen_addr.tls { "https" } else { "http" }
+        );
+        // IPV6_V6ONLY only exists on IPv6 sockets
+        let mut sock_opt = TcpSocketOptions::default();
+        sock_opt.ipv6_only = (listen_addr.family == AddressFamily::Ipv6).then_some(true);
         if listen_addr.tls {
-            if let Some((ref cert_path, ref key_path)) = tls_cert_key {
-                proxy_service
-                    .add_tls(&listen_addr.addr, cert_path, key_path)
-                    .context(format!(
-                        "Failed to add TLS listener on {}",
-                        listen_addr.addr
-                    ))?;
+            if let Some((ref listener_cert, ref ca_cert_path)) = listener_tls {
+                let settings = tls::listener_settings(
+                    listener_cert.clone(),
+                    listen_addr.client_auth,
+                    ca_cert_path,
+                    config_arc.get_listener(listen_addr.port),
+                )
+                .context(format!(
+                    "Failed to add TLS listener on {}",
+                    listen_addr.addr
+                ))?;
+                proxy_service.add_tls_with_settings(&listen_addr.addr, Some(sock_opt), settings);
             } else {
                 eprintln!(
                     "Warning: route on {} has listen_tls but TLS is not enabled in config, falling back to TCP",
                     listen_addr.addr
                 );
-                proxy_service.add_tcp(&listen_addr.addr);
+                proxy_service.add_tcp_with_settings(&listen_addr.addr, sock_opt);
             }
         } else {
-            proxy_service.add_tcp(&listen_addr.addr);
+            proxy_service.add_tcp_with_settings(&listen_addr.addr, sock_opt);
         }
     }
 
+    if let Some(lan_address) = &lan_address {
+        print_lan_urls(&config_arc, lan_address, &listen_addrs)?;
+    }
+
     server.add_service(proxy_service);
+    if let Some(dns_server) = dns_server {
+        println!(
+            "DNS server on: {} (udp) for {}",
+            config_arc
+                .dns
+                .as_ref()
+                .map(|d| d.listen_addr().to_string())
+                .unwrap_or_default(),
+            config_arc.dns_domains().join(", ")
+        );
+        server.add_service(background_service("dns", dns_server));
+    }
+    if let (Some(mdns_responder), Some(lan_address)) = (mdns_responder, &lan_address) {
+        println!(
+            "mDNS: claiming {} as {} on {}",
+            mdns_hosts.join(", "),
+            lan_address.ip,
+            lan_address.interface
+        );
+        server.add_service(background_service("mdns", mdns_responder));
+    }
+    if let Some(rotation) = cert_rotation {
+        server.add_service(background_service("cert rotation", rotation));
+    }
+
+    // Once the listeners are up, check that the installed CA is actually trusted (by this
+    // machine; a CA installed under --install-root is for another system)
+    let mut endpoints = tls_endpoint_addresses(&config_arc);
+    if ipv6_unavailable {
+        for (_, _, ips) in endpoints.iter_mut() {
+            ips.retain(|ip| ip.is_ipv4());
+        }
+    }
+    if !skip_install
+        && install_root.is_none()
+        && let Some((_, port, ips)) = endpoints.first()
+        && let Some(&ip) = ips.first()
+    {
+        let port = *port;
+        std::thread::spawn(move || {
+            if !verify::wait_for_listener(ip, port, std::time::Duration::from_secs(10)) {
+                return;
+            }
+            let connector = match verify::system_trust_connector() {
+                Ok(connector) => connector,
+                Err(e) => {
+                    eprintln!(
+                        "⚠️  Could not check TLS against the system trust store: {:#}",
+                        e
+                    );
+                    return;
+                }
+            };
+            let checks = check_endpoints(&connector, &endpoints);
+            if checks.iter().all(|c| c.error.is_none()) {
+                println!(
+                    "✅ TLS verified against the system trust store for {} host(s)",
+                    endpoints.len()
+                );
+            } else {
+                println!("⚠️  TLS verification against the system trust store failed:");
+                verify::print_checks(&checks);
+                println!("   Run `devrelay ca info` for details.");
+            }
+        });
+    }
 
     println!("Starting DevRelay proxy...\n");
     // Shutdown: Ctrl+C (SIGINT) = fast exit; kill <PID> or SIGTERM = graceful shutdown.
@@ -0,0 +1,551 @@
+use crate::dns::{self, CLASS_ANY, CLASS_IN, Message, Record, TYPE_ANY};
+use crate::lan::LanAddress;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use pingora_core::server::ShutdownWatch;
+use pingora_core::services::background::BackgroundService;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::cmp::Ordering;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time::Instant;
+
+const MDNS_PORT: u16 = 5353;
+const GROUP_V4: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const GROUP_V6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0xfb);
+
+/// TTL RFC 6762 recommends for address records
+const TTL: u32 = 120;
+/// Top bit of the class: "cache flush" in records, "unicast response wanted" in questions
+const CLASS_TOP_BIT: u16 = 0x8000;
+const PROBES: u32 = 3;
+const PROBE_INTERVAL: Duration = Duration::from_millis(250);
+/// Wait after losing a simultaneous probe tiebreak, before probing again (section 8.2)
+const PROBE_DEFER: Duration = Duration::from_secs(1);
+const ANNOUNCEMENTS: u32 = 2;
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+/// Largest multicast DNS message (RFC 6762 section 17)
+const MAX_PACKET: usize = 9000;
+
+/// Multicast DNS responder (RFC 6762) announcing `.local` route hosts with the LAN address, so
+/// phones and tablets on the network resolve them without any hosts file. Names are probed for
+/// first; one that another device already uses is left to it.
+pub struct MdnsResponder {
+    socket: std::net::UdpSocket,
+    /// Lowercase `.local` names
+    hosts: Vec<String>,
+    ip: IpAddr,
+    group: SocketAddr,
+}
+
+impl MdnsResponder {
+    /// Join the mDNS group on the LAN interface now, so errors show up at startup rather than in
+    /// the service.
+    pub fn bind(hosts: Vec<String>, lan: &LanAddress) -> Result<Self> {
+        let (socket, group) = join_group(lan)
+            .with_context(|| format!("Cannot join the mDNS group on {}", lan.interface))?;
+        Ok(Self {
+            socket,
+            hosts,
+            ip: lan.ip,
+            group,
+        })
+    }
+
+    /// Response to a query and where to send it, if it asks for one of `hosts`.
+    fn reply(
+        &self,
+        hosts: &[String],
+        packet: &[u8],
+        client: SocketAddr,
+    ) -> Option<(Vec<u8>, SocketAddr)> {
+        let query = Message::parse(packet)?;
+        if query.is_response() || query.opcode() != 0 {
+            return None;
+        }
+
+        let mut names: Vec<&str> = Vec::new();
+        let mut unicast = false;
+        for question in &query.questions {
+            if !matches!(question.qclass & !CLASS_TOP_BIT, CLASS_IN | CLASS_ANY)
+                || dns::addresses_for(&[self.ip], question.qtype).is_empty()
+            {
+                continue;
+            }
+            let Some(host) = hosts.iter().find(|h| **h == question.name) else {
+                continue;
+            };
+            unicast |= question.qclass & CLASS_TOP_BIT != 0;
+            if !names.contains(&host.as_str()) {
+                names.push(host);
+            }
+        }
+        // No negative answers: other responders on the network may own the rest
+        if names.is_empty() {
+            return None;
+        }
+
+        if client.port() != MDNS_PORT {
+            // One-shot query from a plain resolver (e.g. `dig -p 5353`): a regular DNS reply
+            // echoing the question, with a short TTL (section 6.7)
+            let [question] = query.questions.as_slice() else {
+                return None;
+            };
+            let answers = dns::addresses_for(&[self.ip], question.qtype);
+            return Some((dns::response(packet, &query, 0, &answers, true), client));
+        }
+        let destination = if unicast { client } else { self.group };
+        Some((records(&names, self.ip, TTL), destination))
+    }
+
+    /// Our address record, as it appears on the wire.
+    fn record_data(&self) -> (u16, Vec<u8>) {
+        match self.ip {
+            IpAddr::V4(ip) => (dns::TYPE_A, ip.octets().to_vec()),
+            IpAddr::V6(ip) => (dns::TYPE_AAAA, ip.octets().to_vec()),
+        }
+    }
+
+    /// Names of `hosts` that a response in `packet` gives another address, i.e. that another
+    /// device uses (section 9). Our own records, looped back by multicast, do not count.
+    fn conflicts(&self, hosts: &[String], packet: &[u8]) -> Vec<String> {
+        let Some(message) = Message::parse(packet).filter(Message::is_response) else {
+            return Vec::new();
+        };
+        let Some(records) = message.records(packet) else {
+            return Vec::new();
+        };
+        let (_, rdata) = self.record_data();
+        hosts
+            .iter()
+            .filter(|host| {
+                records
+                    .answers
+                    .iter()
+                    .chain(&records.additional)
+                    .any(|record| {
+                        record.name == **host
+                            && matches!(record.rtype, dns::TYPE_A | dns::TYPE_AAAA)
+                            && record.rdata != rdata
+                    })
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Whether a query in `packet` is another device probing for one of `hosts` with data that
+    /// wins the tiebreak against ours (section 8.2), so we should wait and probe again.
+    fn loses_tiebreak(&self, hosts: &[String], packet: &[u8]) -> bool {
+        let Some(message) = Message::parse(packet).filter(|m| !m.is_response()) else {
+            return false;
+        };
+        let Some(records) = message.records(packet) else {
+            return false;
+        };
+        let (rtype, rdata) = self.record_data();
+        hosts.iter().any(|host| {
+            let theirs: Vec<&Record> = records
+                .authority
+                .iter()
+                .filter(|record| record.name == *host)
+                .collect();
+            !theirs.is_empty() && compare_records(&theirs, rtype, &rdata) == Ordering::Greater
+        })
+    }
+}
+
+/// Compare another prober's records for a name with our single one: by class (without the
+/// cache-flush bit), type and data, lexicographically greatest record first (section 8.2).
+fn compare_records(theirs: &[&Record], rtype: u16, rdata: &[u8]) -> Ordering {
+    let mut theirs: Vec<(u16, u16, &[u8])> = theirs
+        .iter()
+        .map(|r| (r.class & !CLASS_TOP_BIT, r.rtype, r.rdata.as_slice()))
+        .collect();
+    theirs.sort_by(|a, b| b.cmp(a));
+    theirs.as_slice().cmp(&[(CLASS_IN, rtype, rdata)][..])
+}
+
+/// Socket on the mDNS port joined to the group on the LAN interface, and the group address.
+fn join_group(lan: &LanAddress) -> std::io::Result<(std::net::UdpSocket, SocketAddr)> {
+    let (domain, unspecified, group) = match lan.ip {
+        IpAddr::V4(_) => (
+            Domain::IPV4,
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            IpAddr::V4(GROUP_V4),
+        ),
+        IpAddr::V6(_) => (
+            Domain::IPV6,
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            IpAddr::V6(GROUP_V6),
+        ),
+    };
+    let socket = Socket::new(domain, Type::DGRAM, Some(Protocol::UDP))?;
+    // The system's own responder (Avahi, mDNSResponder) usually has the port too
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    if lan.ip.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.bind(&SocketAddr::new(unspecified, MDNS_PORT).into())?;
+
+    // Responses are sent with IP TTL 255, which receivers check (section 11)
+    match lan.ip {
+        IpAddr::V4(ip) => {
+            socket.join_multicast_v4(&GROUP_V4, &ip)?;
+            socket.set_multicast_if_v4(&ip)?;
+            socket.set_multicast_ttl_v4(255)?;
+        }
+        IpAddr::V6(_) => {
+            socket.join_multicast_v6(&GROUP_V6, lan.index)?;
+            socket.set_multicast_if_v6(lan.index)?;
+            socket.set_multicast_hops_v6(255)?;
+        }
+    }
+    socket.set_nonblocking(true)?;
+    Ok((socket.into(), SocketAddr::new(group, MDNS_PORT)))
+}
+
+/// Probe query asking whether anyone has the names (QU, type ANY), with the records we intend to
+/// claim in the authority section (section 8.1).
+fn probe(names: &[String], ip: IpAddr) -> Vec<u8> {
+    let count = (names.len() as u16).to_be_bytes();
+    // ID 0, a standard query
+    let mut out = vec![
+        0, 0, 0, 0, count[0], count[1], 0, 0, count[0], count[1], 0, 0,
+    ];
+    for name in names {
+        dns::write_name(&mut out, name);
+        out.extend_from_slice(&TYPE_ANY.to_be_bytes());
+        out.extend_from_slice(&(CLASS_IN | CLASS_TOP_BIT).to_be_bytes());
+    }
+    for name in names {
+        dns::write_address_record(
+            &mut out,
+            |out| dns::write_name(out, name),
+            ip,
+            CLASS_IN,
+            TTL,
+        );
+    }
+    out
+}
+
+/// Random delay of up to 250 ms before the first probe, so devices starting together do not
+/// probe in lockstep.
+fn probe_delay() -> Duration {
+    let mut byte = [0u8];
+    let _ = openssl::rand::rand_bytes(&mut byte);
+    Duration::from_millis(u64::from(byte[0]) * 250 / 255)
+}
+
+/// Unsolicited response with an address record per name. The records have the cache-flush bit
+/// set, since the names are ours alone.
+fn records(names: &[&str], ip: IpAddr, ttl: u32) -> Vec<u8> {
+    // ID 0, QR and AA, no questions
+    let mut out = vec![0, 0, 0x84, 0, 0, 0];
+    out.extend_from_slice(&(names.len() as u16).to_be_bytes());
+    out.extend_from_slice(&[0, 0, 0, 0]);
+    for name in names {
+        dns::write_address_record(
+            &mut out,
+            |out| dns::write_name(out, name),
+            ip,
+            CLASS_IN | CLASS_TOP_BIT,
+            ttl,
+        );
+    }
+    out
+}
+
+impl MdnsResponder {
+    /// Probe for `hosts` before claiming them, dropping names another device already has.
+    /// Returns None on shutdown.
+    async fn probe_names(
+        &self,
+        socket: &UdpSocket,
+        mut hosts: Vec<String>,
+        shutdown: &mut ShutdownWatch,
+    ) -> Option<Vec<String>> {
+        let mut next_probe = Instant::now() + probe_delay();
+        let mut probes = 0;
+        let mut buf = vec![0u8; MAX_PACKET];
+        while !hosts.is_empty() {
+            tokio::select! {
+                _ = shutdown.changed() => return None,
+                _ = tokio::time::sleep_until(next_probe) => {
+                    if probes == PROBES {
+                        break;
+                    }
+                    probes += 1;
+                    next_probe = Instant::now() + PROBE_INTERVAL;
+                    if let Err(e) = socket.send_to(&probe(&hosts, self.ip), self.group).await {
+                        eprintln!("mDNS probe failed: {}", e);
+                    }
+                }
+                received = socket.recv_from(&mut buf) => {
+                    let Ok((len, _)) = received else {
+                        continue;
+                    };
+                    let packet = &buf[..len];
+                    let taken = self.conflicts(&hosts, packet);
+                    if !taken.is_empty() {
+                        for name in &taken {
+                            println!(
+                                "⚠️  mDNS: {} is already in use on the network; not announcing it",
+                                name
+                            );
+                        }
+                        hosts.retain(|host| !taken.contains(host));
+                    } else if self.loses_tiebreak(&hosts, packet) {
+                        probes = 0;
+                        next_probe = Instant::now() + PROBE_DEFER;
+                    }
+                }
+            }
+        }
+        Some(hosts)
+    }
+}
+
+#[async_trait]
+impl BackgroundService for MdnsResponder {
+    async fn start(&self, mut shutdown: ShutdownWatch) {
+        let socket = match self.socket.try_clone().map(UdpSocket::from_std) {
+            Ok(Ok(socket)) => socket,
+            Ok(Err(e)) | Err(e) => {
+                eprintln!("mDNS responder failed to start: {}", e);
+                return;
+            }
+        };
+        let Some(mut hosts) = self
+            .probe_names(&socket, self.hosts.clone(), &mut shutdown)
+            .await
+        else {
+            return;
+        };
+        // Announced a few times, a second apart (section 8.3)
+        let mut announce = tokio::time::interval(ANNOUNCE_INTERVAL);
+        let mut announced = 0;
+        let mut buf = vec![0u8; MAX_PACKET];
+        while !hosts.is_empty() {
+            let names: Vec<&str> = hosts.iter().map(String::as_str).collect();
+            tokio::select! {
+                _ = shutdown.changed() => {
+                    // Goodbye: TTL 0 makes caches drop the records (section 10.1)
+                    let _ = socket.send_to(&records(&names, self.ip, 0), self.group).await;
+                    return;
+                }
+                _ = announce.tick(), if announced < ANNOUNCEMENTS => {
+                    announced += 1;
+                    if let Err(e) = socket.send_to(&records(&names, self.ip, TTL), self.group).await {
+                        eprintln!("mDNS announcement failed: {}", e);
+                    }
+                }
+                received = socket.recv_from(&mut buf) => match received {
+                    Ok((len, client)) => {
+                        let packet = &buf[..len];
+                        if let Some((response, destination)) = self.reply(&hosts, packet, client) {
+                            let _ = socket.send_to(&response, destination).await;
+                        }
+                        // Another device started using one of our names
+                        let taken = self.conflicts(&hosts, packet);
+                        for name in &taken {
+                            println!("⚠️  mDNS: {} is now also used by another device on the network; no longer announcing it", name);
+                        }
+                        hosts.retain(|host| !taken.contains(host));
+                    }
+                    Err(e) => eprintln!("mDNS responder receive failed: {}", e),
+                },
+            }
+        }
+        let _ = shutdown.changed().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAN_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+
+    fn responder() -> MdnsResponder {
+        MdnsResponder {
+            socket: std::net::UdpSocket::bind("127.0.0.1:0").unwrap(),
+            hosts: hosts(),
+            ip: LAN_IP,
+            group: SocketAddr::new(IpAddr::V4(GROUP_V4), MDNS_PORT),
+        }
+    }
+
+    fn hosts() -> Vec<String> {
+        vec!["myapp.local".to_string()]
+    }
+
+    fn query(name: &str, qtype: u16, qclass: u16) -> Vec<u8> {
+        let mut packet = vec![0xAB, 0xCD, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        dns::write_name(&mut packet, name);
+        packet.extend_from_slice(&qtype.to_be_bytes());
+        packet.extend_from_slice(&qclass.to_be_bytes());
+        packet
+    }
+
+    fn client(port: u16) -> SocketAddr {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 30)), port)
+    }
+
+    #[test]
+    fn records_claim_names_with_cache_flush() {
+        let packet = records(&["myapp.local", "api.local"], LAN_IP, TTL);
+        let message = Message::parse(&packet).unwrap();
+        assert!(message.is_response());
+        // ID 0 and AA
+        assert_eq!(&packet[..4], &[0, 0, 0x84, 0]);
+        assert!(message.questions.is_empty());
+
+        let answers = message.records(&packet).unwrap().answers;
+        let summary: Vec<(&str, u16, u16, &[u8])> = answers
+            .iter()
+            .map(|r| (r.name.as_str(), r.rtype, r.class, r.rdata.as_slice()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("myapp.local", dns::TYPE_A, 0x8001, &[192, 168, 1, 20][..]),
+                ("api.local", dns::TYPE_A, 0x8001, &[192, 168, 1, 20][..]),
+            ]
+        );
+        let ttl_at = 12 + "myapp.local".len() + 2 + 4;
+        assert_eq!(&packet[ttl_at..ttl_at + 4], &TTL.to_be_bytes());
+    }
+
+    #[test]
+    fn reply_multicasts_answers_to_mdns_queries() {
+        let responder = responder();
+        let (response, destination) = responder
+            .reply(
+                &hosts(),
+                &query("MyApp.Local", dns::TYPE_A, CLASS_IN),
+                client(5353),
+            )
+            .unwrap();
+        assert_eq!(destination, responder.group);
+        assert_eq!(response, records(&["myapp.local"], LAN_IP, TTL));
+    }
+
+    #[test]
+    fn reply_unicasts_when_the_question_asks_for_it() {
+        let responder = responder();
+        let qu = query("myapp.local", dns::TYPE_ANY, CLASS_IN | CLASS_TOP_BIT);
+        let (response, destination) = responder.reply(&hosts(), &qu, client(5353)).unwrap();
+        assert_eq!(destination, client(5353));
+        assert_eq!(response, records(&["myapp.local"], LAN_IP, TTL));
+    }
+
+    #[test]
+    fn reply_answers_legacy_unicast_queries_as_plain_dns() {
+        let responder = responder();
+        let packet = query("myapp.local", dns::TYPE_A, CLASS_IN);
+        let (response, destination) = responder.reply(&hosts(), &packet, client(40000)).unwrap();
+        assert_eq!(destination, client(40000));
+
+        let message = Message::parse(&response).unwrap();
+        assert_eq!(message.id, 0xABCD);
+        assert_eq!(message.questions.len(), 1);
+        let answers = message.records(&response).unwrap().answers;
+        assert_eq!(answers.len(), 1);
+        // No cache-flush bit in unicast DNS
+        assert_eq!(answers[0].class, CLASS_IN);
+        assert_eq!(answers[0].rdata, [192, 168, 1, 20]);
+    }
+
+    #[test]
+    fn reply_ignores_what_is_not_ours() {
+        let responder = responder();
+        let reply = |packet: &[u8]| {
+            responder
+                .reply(&hosts(), packet, client(5353))
+                .map(|(r, _)| r)
+        };
+        assert!(reply(&query("other.local", dns::TYPE_A, CLASS_IN)).is_none());
+        // No AAAA record for an IPv4 address, and no negative answer either
+        assert!(reply(&query("myapp.local", dns::TYPE_AAAA, CLASS_IN)).is_none());
+        assert!(reply(&query("myapp.local", dns::TYPE_A, 3)).is_none());
+        assert!(reply(&records(&["myapp.local"], LAN_IP, TTL)).is_none());
+        // Names dropped after a conflict are no longer answered
+        assert!(
+            responder
+                .reply(
+                    &[],
+                    &query("myapp.local", dns::TYPE_A, CLASS_IN),
+                    client(5353)
+                )
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn probe_asks_for_names_and_proposes_records() {
+        let packet = probe(&hosts(), LAN_IP);
+        let message = Message::parse(&packet).unwrap();
+        assert!(!message.is_response());
+        let [question] = message.questions.as_slice() else {
+            panic!("expected one question");
+        };
+        assert_eq!(question.name, "myapp.local");
+        assert_eq!(question.qtype, TYPE_ANY);
+        assert_eq!(question.qclass, CLASS_IN | CLASS_TOP_BIT);
+
+        let records = message.records(&packet).unwrap();
+        assert!(records.answers.is_empty());
+        let [record] = records.authority.as_slice() else {
+            panic!("expected one authority record");
+        };
+        assert_eq!(record.name, "myapp.local");
+        assert_eq!((record.rtype, record.class), (dns::TYPE_A, CLASS_IN));
+        assert_eq!(record.rdata, [192, 168, 1, 20]);
+    }
+
+    #[test]
+    fn responses_with_other_addresses_are_conflicts() {
+        let responder = responder();
+        let other = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 99));
+        assert_eq!(
+            responder.conflicts(&hosts(), &records(&["myapp.local"], other, TTL)),
+            hosts()
+        );
+        // Our own records, looped back
+        assert!(
+            responder
+                .conflicts(&hosts(), &records(&["myapp.local"], LAN_IP, TTL))
+                .is_empty()
+        );
+        assert!(
+            responder
+                .conflicts(&hosts(), &records(&["other.local"], other, TTL))
+                .is_empty()
+        );
+        // Queries, even with records, are not
+        assert!(
+            responder
+                .conflicts(&hosts(), &probe(&hosts(), other))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn simultaneous_probe_with_greater_data_wins() {
+        let responder = responder();
+        let higher = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 30));
+        let lower = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10));
+        assert!(responder.loses_tiebreak(&hosts(), &probe(&hosts(), higher)));
+        assert!(!responder.loses_tiebreak(&hosts(), &probe(&hosts(), lower)));
+        assert!(!responder.loses_tiebreak(&hosts(), &probe(&hosts(), LAN_IP)));
+        // AAAA sorts after A
+        let v6 = IpAddr::V6(Ipv6Addr::LOCALHOST);
+        assert!(responder.loses_tiebreak(&hosts(), &probe(&hosts(), v6)));
+        let other_name = vec!["other.local".to_string()];
+        assert!(!responder.loses_tiebreak(&hosts(), &probe(&other_name, higher)));
+    }
+}
@@ -1,45 +1,150 @@
-use crate::config::{Config, Route};
+use crate::acme::AcmeServer;
+use crate::config::{AddressFamily, ClientAuth, Config, Route};
+use crate::tls::{BackendTls, ClientCert};
+use anyhow::Result;
 use async_trait::async_trait;
 use pingora_core::upstreams::peer::HttpPeer;
 use pingora_proxy::{ProxyHttp, Session};
+use std::collections::HashMap;
+use std::net::IpAddr;
 use std::sync::Arc;
 
+/// Headers carrying the verified client certificate to the backend (mTLS routes).
+const CLIENT_CERT_SUBJECT_HEADER: &str = "X-Client-Cert-Subject";
+const CLIENT_CERT_FINGERPRINT_HEADER: &str = "X-Client-Cert-Fingerprint";
+
 pub struct DevRelayProxy {
     config: Arc<Config>,
     quiet: bool,
+    /// Backend TLS settings per route host, loaded at startup
+    backend_tls: HashMap<String, BackendTls>,
+    /// Local ACME server, answering requests for its own host
+    acme: Option<Arc<AcmeServer>>,
+    /// Address exposed in `--lan` mode; requests to it are routed by port
+    lan_address: Option<IpAddr>,
 }
 
 impl DevRelayProxy {
-    pub fn new(config: Arc<Config>, quiet: bool) -> Self {
-        Self { config, quiet }
+    pub fn new(config: Arc<Config>, quiet: bool, acme: Option<Arc<AcmeServer>>) -> Result<Self> {
+        let mut backend_tls = HashMap::new();
+        for route in &config.routes {
+            let Some(options) = &route.backend_tls_options else {
+                continue;
+            };
+            if !route.backend_tls {
+                println!(
+                    "⚠️  {} has backend_tls_options but backend_tls is off; they are ignored.",
+                    route.host
+                );
+                continue;
+            }
+            let settings = BackendTls::load(options).map_err(|e| {
+                e.context(format!("Invalid backend_tls_options for {}", route.host))
+            })?;
+            backend_tls.insert(route.host.clone(), settings);
+        }
+
+        Ok(Self {
+            config,
+            quiet,
+            backend_tls,
+            acme,
+            lan_address: None,
+        })
+    }
+
+    pub fn with_lan_address(mut self, lan_address: Option<IpAddr>) -> Self {
+        self.lan_address = lan_address;
+        self
     }
 
-    fn get_backend_for_host(&self, host: &str) -> Option<&Route> {
-        self.config.get_route_by_host(host)
+    fn get_backend_for_host(&self, session: &Session, host: &str) -> Option<&Route> {
+        self.config.get_route_by_host(host).or_else(|| {
+            // Devices on the LAN can't resolve route hosts and connect to the address directly
+            let lan_address = self.lan_address?;
+            if host_ip(host)? != lan_address {
+                return None;
+            }
+            let port = session.server_addr()?.as_inet()?.port();
+            self.config.lan_route(port)
+        })
     }
 }
 
+/// IP address of a Host header value such as "192.168.1.20:8443" or "[fd00::1]".
+fn host_ip(host: &str) -> Option<IpAddr> {
+    let host = match host.rsplit_once(':') {
+        Some((ip, port)) if port.parse::<u16>().is_ok() && !ip.ends_with(':') => ip,
+        _ => host,
+    };
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .ok()
+}
+
+fn request_host(session: &Session) -> &str {
+    let req = session.req_header();
+    req.headers
+        .get("Host")
+        .and_then(|h| h.to_str().ok())
+        // HTTP/2 requests (ALPN "h2") carry the host in the :authority pseudo-header instead
+        .or_else(|| req.uri.host())
+        .unwrap_or("")
+}
+
+/// Client certificate verified during the TLS handshake of this connection, if any.
+fn client_cert(session: &Session) -> Option<&ClientCert> {
+    session
+        .digest()?
+        .ssl_digest
+        .as_ref()?
+        .extension
+        .get::<ClientCert>()
+}
+
 #[async_trait]
 impl ProxyHttp for DevRelayProxy {
     type CTX = ();
 
     fn new_ctx(&self) -> Self::CTX {}
 
+    async fn request_filter(
+        &self,
+        session: &mut Session,
+        _ctx: &mut Self::CTX,
+    ) -> pingora_core::Result<bool> {
+        if let Some(acme) = &self.acme {
+            let host = request_host(session).to_string();
+            if acme.serves(&host) {
+                acme.serve(session, &host).await?;
+                return Ok(true);
+            }
+        }
+
+        // Routes sharing a port with non-mTLS routes only request a certificate during the
+        // handshake, so enforce `client_auth: required` per request as well
+        let requires_cert = self
+            .get_backend_for_host(session, request_host(session))
+            .is_some_and(|route| route.client_auth == ClientAuth::Required);
+
+        if requires_cert && client_cert(session).is_none() {
+            session.respond_error(403).await?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
     async fn upstream_peer(
         &self,
         session: &mut Session,
         _ctx: &mut Self::CTX,
     ) -> pingora_core::Result<Box<HttpPeer>> {
         // Get the Host header to determine routing
-        let host = session
-            .req_header()
-            .headers
-            .get("Host")
-            .and_then(|h| h.to_str().ok())
-            .unwrap_or("");
+        let host = request_host(session);
 
         // Find the matching route
-        let route = self.get_backend_for_host(host).ok_or_else(|| {
+        let route = self.get_backend_for_host(session, host).ok_or_else(|| {
             pingora_core::Error::explain(
                 pingora_core::ErrorType::HTTPStatus(404),
                 format!("No route configured for host: {}", host),
@@ -47,11 +152,18 @@ impl ProxyHttp for DevRelayProxy {
         })?;
 
         // Create peer for the backend
-        let peer = Box::new(HttpPeer::new(
+        let backend_tls = self.backend_tls.get(&route.host);
+        let sni = backend_tls
+            .and_then(|t| t.sni())
+            .unwrap_or(route.backend.as_str());
+        let mut peer = Box::new(HttpPeer::new(
             (route.backend.as_str(), route.backend_port),
             route.backend_tls,
-            route.backend.clone(),
+            sni.to_string(),
         ));
+        if let Some(backend_tls) = backend_tls {
+            backend_tls.apply(&mut peer);
+        }
 
         if !self.quiet {
             let path = session.req_header().uri.path();
@@ -66,12 +178,27 @@ impl ProxyHttp for DevRelayProxy {
 
     async fn upstream_request_filter(
         &self,
-        _session: &mut Session,
-        _upstream_request: &mut pingora::http::RequestHeader,
+        session: &mut Session,
+        upstream_request: &mut pingora::http::RequestHeader,
         _ctx: &mut Self::CTX,
     ) -> pingora_core::Result<()> {
         // Forward the original Host header to the backend
         // This is useful if the backend needs to know the original host
+
+        // Never trust client-supplied certificate headers; only set them from the handshake
+        upstream_request.remove_header(CLIENT_CERT_SUBJECT_HEADER);
+        upstream_request.remove_header(CLIENT_CERT_FINGERPRINT_HEADER);
+
+        let client_auth = self
+            .get_backend_for_host(session, request_host(session))
+            .map_or(ClientAuth::Off, |route| route.client_auth);
+        if client_auth != ClientAuth::Off
+            && let Some(cert) = client_cert(session)
+        {
+            upstream_request.insert_header(CLIENT_CERT_SUBJECT_HEADER, cert.subject.as_str())?;
+            upstream_request
+                .insert_header(CLIENT_CERT_FINGERPRINT_HEADER, cert.fingerprint.as_str())?;
+        }
         Ok(())
     }
 
@@ -101,10 +228,32 @@ impl ProxyHttp for DevRelayProxy {
 
 pub struct ListenAddr {
     pub addr: String,
+    /// Address bound to
+    pub ip: IpAddr,
+    pub port: u16,
+    pub family: AddressFamily,
     pub tls: bool,
+    /// Client certificate policy for the whole port (see `get_listen_addresses`)
+    pub client_auth: ClientAuth,
 }
 
-pub fn get_listen_addresses(config: &Config) -> Vec<ListenAddr> {
+impl ListenAddr {
+    /// Bind a socket with the options the listener will get, to catch errors before Pingora
+    /// (which panics on them) does.
+    pub fn try_bind(&self) -> std::io::Result<()> {
+        use socket2::{Domain, Socket, Type};
+        let addr = std::net::SocketAddr::new(self.ip, self.port);
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+        if self.family == AddressFamily::Ipv6 {
+            socket.set_only_v6(true)?;
+        }
+        socket.set_reuse_address(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(1)
+    }
+}
+
+pub fn get_listen_addresses(config: &Config, lan_address: Option<IpAddr>) -> Vec<ListenAddr> {
     // Collect unique ports and whether they need TLS
     // If any route on a port has listen_tls, the whole port is TLS
     let mut port_tls: std::collections::HashMap<u16, bool> = std::collections::HashMap::new();
@@ -114,16 +263,219 @@ pub fn get_listen_addresses(config: &Config) -> Vec<ListenAddr> {
             *entry = true;
         }
     }
+    // The ACME directory is served over HTTPS, which makes its port a TLS port
+    if let Some(acme) = &config.acme
+        && config.tls.enabled
+    {
+        port_tls.insert(acme.port, true);
+    }
+
+    // Addresses each port is bound to; `None` for the default (loopback) addresses
+    let mut port_binds: std::collections::HashMap<u16, Vec<Option<IpAddr>>> =
+        std::collections::HashMap::new();
+    for route in &config.routes {
+        port_binds
+            .entry(route.port)
+            .or_default()
+            .push(config.route_bind_address(route));
+    }
+    if let Some(acme) = &config.acme
+        && config.tls.enabled
+    {
+        port_binds
+            .entry(acme.port)
+            .or_default()
+            .push(config.bind_address);
+    }
 
+    // Without a bind address, one loopback socket per address family (plus the LAN address in
+    // `--lan` mode); IPv6 sockets are IPv6-only so they don't clash with IPv4 ones on the port
+    let families = config.address_families();
     let mut result: Vec<ListenAddr> = port_tls
         .into_iter()
-        .map(|(port, tls)| {
-            let proto = if tls { "https" } else { "http" };
-            let addr = format!("0.0.0.0:{}", port);
-            println!("Listening on: {} ({})", addr, proto);
-            ListenAddr { addr, tls }
+        .flat_map(|(port, tls)| {
+            let client_auth = port_client_auth(config, port);
+            let binds = &port_binds[&port];
+            let mut ips: Vec<IpAddr> = binds.iter().flatten().copied().collect();
+            if binds.contains(&None) {
+                ips.extend(families.iter().map(|family| family.loopback()));
+                ips.extend(lan_address);
+            }
+            ips.sort();
+            ips.dedup();
+            ips.into_iter().map(move |ip| ListenAddr {
+                addr: std::net::SocketAddr::new(ip, port).to_string(),
+                ip,
+                port,
+                family: if ip.is_ipv6() {
+                    AddressFamily::Ipv6
+                } else {
+                    AddressFamily::Ipv4
+                },
+                tls,
+                client_auth,
+            })
         })
         .collect();
-    result.sort_by(|a, b| a.addr.cmp(&b.addr));
+    result.sort_by_key(|l| (l.port, l.family == AddressFamily::Ipv6));
     result
 }
+
+/// Client certificates are demanded in the handshake only if every route on the port requires
+/// them, and requested if any route uses them; per-route enforcement happens in `request_filter`.
+fn port_client_auth(config: &Config, port: u16) -> ClientAuth {
+    let modes: Vec<ClientAuth> = config
+        .routes
+        .iter()
+        .filter(|r| r.port == port)
+        .map(|r| r.client_auth)
+        .collect();
+
+    // ACME clients don't present certificates, so a port shared with the directory can't demand one
+    let serves_acme = config.acme.as_ref().is_some_and(|acme| acme.port == port);
+
+    if modes.iter().all(|m| *m == ClientAuth::Required) && !serves_acme {
+        ClientAuth::Required
+    } else if modes.iter().any(|m| *m != ClientAuth::Off) {
+        ClientAuth::Optional
+    } else {
+        ClientAuth::Off
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AcmeConfig;
+
+    fn route(host: &str, port: u16, listen_tls: bool, bind_address: Option<&str>) -> Route {
+        Route {
+            host: host.to_string(),
+            port,
+            listen_tls,
+            backend: "localhost".to_string(),
+            backend_port: 3000,
+            backend_tls: false,
+            client_auth: ClientAuth::Off,
+            backend_tls_options: None,
+            bind_address: bind_address.map(|ip| ip.parse().unwrap()),
+        }
+    }
+
+    fn acme(port: u16) -> Option<AcmeConfig> {
+        Some(AcmeConfig {
+            host: "acme.test".to_string(),
+            port,
+            auto_approve: true,
+            cert_days: 30,
+        })
+    }
+
+    /// `(addr, tls)` of every listener, in order.
+    fn listeners(config: &Config, lan_address: Option<&str>) -> Vec<(String, bool)> {
+        let lan_address = lan_address.map(|ip| ip.parse().unwrap());
+        get_listen_addresses(config, lan_address)
+            .into_iter()
+            .map(|l| (l.addr, l.tls))
+            .collect()
+    }
+
+    fn addrs(addrs: &[&str], tls: bool) -> Vec<(String, bool)> {
+        addrs.iter().map(|addr| (addr.to_string(), tls)).collect()
+    }
+
+    #[test]
+    fn unbound_routes_listen_on_loopback() {
+        let mut config = Config {
+            routes: vec![
+                route("a.test", 8080, false, None),
+                route("b.test", 8080, false, None),
+            ],
+            ..Config::default()
+        };
+        assert_eq!(
+            listeners(&config, None),
+            addrs(&["127.0.0.1:8080", "[::1]:8080"], false)
+        );
+
+        config.address_families = vec![AddressFamily::Ipv6];
+        assert_eq!(listeners(&config, None), addrs(&["[::1]:8080"], false));
+    }
+
+    #[test]
+    fn mixed_bound_and_unbound_routes_share_a_port() {
+        let config = Config {
+            routes: vec![
+                route("a.test", 8443, false, Some("127.0.0.3")),
+                route("b.test", 8443, true, None),
+                route("c.test", 8443, false, Some("127.0.0.3")),
+            ],
+            ..Config::default()
+        };
+        // One TLS route makes the whole port TLS; the bind address is added to the loopbacks
+        assert_eq!(
+            listeners(&config, None),
+            addrs(&["127.0.0.1:8443", "127.0.0.3:8443", "[::1]:8443"], true)
+        );
+    }
+
+    #[test]
+    fn bound_routes_skip_loopback() {
+        let config = Config {
+            routes: vec![
+                route("a.test", 8443, true, Some("127.0.0.3")),
+                route("b.test", 8443, true, Some("127.0.0.4")),
+            ],
+            ..Config::default()
+        };
+        assert_eq!(
+            listeners(&config, Some("192.0.2.10")),
+            addrs(&["127.0.0.3:8443", "127.0.0.4:8443"], true)
+        );
+    }
+
+    #[test]
+    fn top_level_bind_address_applies_to_routes_and_acme() {
+        let config = Config {
+            routes: vec![route("a.test", 8443, true, None)],
+            acme: acme(9443),
+            bind_address: Some("127.0.0.2".parse().unwrap()),
+            ..Config::default()
+        };
+        let mut expected = addrs(&["127.0.0.2:8443"], true);
+        expected.extend(addrs(&["127.0.0.2:9443"], true));
+        assert_eq!(listeners(&config, None), expected);
+    }
+
+    #[test]
+    fn acme_makes_a_shared_port_tls() {
+        let mut config = Config {
+            routes: vec![route("a.test", 8443, false, Some("127.0.0.3"))],
+            acme: acme(8443),
+            ..Config::default()
+        };
+        // The directory listens on loopback next to the route's bind address
+        assert_eq!(
+            listeners(&config, None),
+            addrs(&["127.0.0.1:8443", "127.0.0.3:8443", "[::1]:8443"], true)
+        );
+
+        // Without TLS there is no directory, so the port stays plain
+        config.tls.enabled = false;
+        assert_eq!(listeners(&config, None), addrs(&["127.0.0.3:8443"], false));
+    }
+
+    #[test]
+    fn lan_address_extends_unbound_ports() {
+        let config = Config {
+            routes: vec![
+                route("a.test", 8080, false, None),
+                route("b.test", 8443, true, Some("127.0.0.3")),
+            ],
+            ..Config::default()
+        };
+        let mut expected = addrs(&["127.0.0.1:8080", "192.0.2.10:8080", "[::1]:8080"], false);
+        expected.extend(addrs(&["127.0.0.3:8443"], true));
+        assert_eq!(listeners(&config, Some("192.0.2.10")), expected);
+    }
+}
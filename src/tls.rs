@@ -0,0 +1,347 @@
+use crate::certs::CertManager;
+use crate::config::{BackendTlsOptions, ClientAuth, ListenerConfig, TlsVersion, expand_home};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use pingora_core::listeners::TlsAccept;
+use pingora_core::listeners::tls::TlsSettings;
+use pingora_core::protocols::ALPN;
+use pingora_core::protocols::tls::{CaType, TlsRef};
+use pingora_core::server::ShutdownWatch;
+use pingora_core::services::background::BackgroundService;
+use pingora_core::tls::ext;
+use pingora_core::tls::hash::MessageDigest;
+use pingora_core::tls::pkey::{PKey, Private};
+use pingora_core::tls::ssl::{SslVerifyMode, SslVersion};
+use pingora_core::tls::x509::{X509, X509VerifyResult};
+use pingora_core::upstreams::peer::HttpPeer;
+use pingora_core::utils::tls::CertKey;
+use std::any::Any;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Verified client certificate presented during the TLS handshake (attached to the `SslDigest`).
+pub struct ClientCert {
+    /// Subject distinguished name, e.g. "CN=alice,O=DevRelay"
+    pub subject: String,
+    /// Hex SHA-256 fingerprint of the certificate
+    pub fingerprint: String,
+}
+
+/// The certificate served by TLS listeners. It is handed out per handshake so a rotated
+/// certificate takes effect for new connections without restarting the listeners.
+pub struct ListenerCert {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: RwLock<Arc<(Vec<X509>, PKey<Private>)>>,
+}
+
+impl ListenerCert {
+    pub fn load(cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> Result<Self> {
+        let cert_path = cert_path.into();
+        let key_path = key_path.into();
+        let current = read_cert_and_key(&cert_path, &key_path)?;
+        Ok(Self {
+            cert_path,
+            key_path,
+            current: RwLock::new(Arc::new(current)),
+        })
+    }
+
+    /// Re-read the certificate and key after they were re-issued on disk.
+    pub fn reload(&self) -> Result<()> {
+        let current = read_cert_and_key(&self.cert_path, &self.key_path)?;
+        *self.current.write().unwrap() = Arc::new(current);
+        Ok(())
+    }
+
+    fn current(&self) -> Arc<(Vec<X509>, PKey<Private>)> {
+        self.current.read().unwrap().clone()
+    }
+}
+
+fn read_cert_and_key(cert_path: &Path, key_path: &Path) -> Result<(Vec<X509>, PKey<Private>)> {
+    let cert_pem = std::fs::read(cert_path)
+        .with_context(|| format!("Failed to read cert file {}", cert_path.display()))?;
+    let chain = X509::stack_from_pem(&cert_pem)
+        .with_context(|| format!("Failed to parse cert file {}", cert_path.display()))?;
+    if chain.is_empty() {
+        anyhow::bail!("No certificate found in {}", cert_path.display());
+    }
+    let key_pem = std::fs::read(key_path)
+        .with_context(|| format!("Failed to read key file {}", key_path.display()))?;
+    let key = PKey::private_key_from_pem(&key_pem)
+        .with_context(|| format!("Failed to parse key file {}", key_path.display()))?;
+    Ok((chain, key))
+}
+
+/// Serves the current listener certificate and records the verified client certificate,
+/// if any, so routes can forward it upstream.
+struct ListenerCallbacks {
+    cert: Arc<ListenerCert>,
+}
+
+#[async_trait]
+impl TlsAccept for ListenerCallbacks {
+    async fn certificate_callback(&self, ssl: &mut TlsRef) {
+        let current = self.cert.current();
+        let (chain, key) = current.as_ref();
+        if let Err(e) = ext::ssl_use_certificate(ssl, &chain[0])
+            .and_then(|_| ext::ssl_use_private_key(ssl, key))
+        {
+            eprintln!("Failed to set listener certificate: {}", e);
+            return;
+        }
+        for cert in &chain[1..] {
+            let _ = ext::ssl_add_chain_cert(ssl, cert);
+        }
+    }
+
+    async fn handshake_complete_callback(
+        &self,
+        ssl: &TlsRef,
+    ) -> Option<Arc<dyn Any + Send + Sync>> {
+        let cert = ssl.peer_certificate()?;
+        if ssl.verify_result() != X509VerifyResult::OK {
+            return None;
+        }
+
+        let subject = cert
+            .subject_name()
+            .entries()
+            .map(|entry| {
+                let key = entry.object().nid().short_name().unwrap_or("?");
+                let value = entry
+                    .data()
+                    .as_utf8()
+                    .map(|v| v.to_string())
+                    .unwrap_or_default();
+                format!("{}={}", key, value)
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let fingerprint = cert
+            .digest(MessageDigest::sha256())
+            .map(|d| d.iter().map(|b| format!("{:02x}", b)).collect())
+            .unwrap_or_default();
+
+        Some(Arc::new(ClientCert {
+            subject,
+            fingerprint,
+        }))
+    }
+}
+
+/// TLS settings for a listener serving `cert`. With client auth enabled, client certificates
+/// chained to the CA at `ca_cert_path` are requested (`Optional`) or demanded (`Required`).
+/// A `policy` overrides the protocol versions, ciphers, groups and ALPN of the default
+/// (Mozilla intermediate) configuration.
+pub fn listener_settings(
+    cert: Arc<ListenerCert>,
+    client_auth: ClientAuth,
+    ca_cert_path: &str,
+    policy: Option<&ListenerConfig>,
+) -> Result<TlsSettings> {
+    let mut settings = TlsSettings::with_callbacks(Box::new(ListenerCallbacks { cert }))
+        .context("Failed to create TLS settings")?;
+
+    if client_auth != ClientAuth::Off {
+        let ca_pem = std::fs::read(ca_cert_path).context("Failed to read CA certificate")?;
+        let ca_cert = X509::from_pem(&ca_pem).context("Failed to parse CA certificate")?;
+
+        settings
+            .add_client_ca(&ca_cert)
+            .context("Failed to advertise client CA")?;
+        settings
+            .cert_store_mut()
+            .add_cert(ca_cert)
+            .context("Failed to add CA to client verification store")?;
+        // Required for session resumption once peer verification is on
+        settings.set_session_id_context(b"devrelay")?;
+
+        let mode = if client_auth == ClientAuth::Required {
+            SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT
+        } else {
+            SslVerifyMode::PEER
+        };
+        settings.set_verify(mode);
+    }
+
+    if let Some(policy) = policy {
+        apply_policy(&mut settings, policy)
+            .with_context(|| format!("Invalid TLS policy for listener port {}", policy.port))?;
+    }
+
+    Ok(settings)
+}
+
+fn apply_policy(settings: &mut TlsSettings, policy: &ListenerConfig) -> Result<()> {
+    if let Some(version) = policy.min_version {
+        settings.set_min_proto_version(Some(ssl_version(version)))?;
+    }
+    if let Some(version) = policy.max_version {
+        settings.set_max_proto_version(Some(ssl_version(version)))?;
+    }
+    if let Some(ciphers) = &policy.ciphers {
+        settings
+            .set_cipher_list(ciphers)
+            .with_context(|| format!("Unknown ciphers: {}", ciphers))?;
+    }
+    if let Some(ciphersuites) = &policy.ciphersuites {
+        settings
+            .set_ciphersuites(ciphersuites)
+            .with_context(|| format!("Unknown TLS 1.3 cipher suites: {}", ciphersuites))?;
+    }
+    if !policy.groups.is_empty() {
+        let groups = policy.groups.join(":");
+        settings
+            .set_groups_list(&groups)
+            .with_context(|| format!("Unknown groups: {}", groups))?;
+    }
+    if let Some(alpn) = alpn(&policy.alpn)? {
+        settings.set_alpn(alpn);
+    }
+    Ok(())
+}
+
+fn ssl_version(version: TlsVersion) -> SslVersion {
+    match version {
+        TlsVersion::Tls12 => SslVersion::TLS1_2,
+        TlsVersion::Tls13 => SslVersion::TLS1_3,
+    }
+}
+
+/// ALPN setting for the configured protocol list (HTTP/2 is preferred when both are offered).
+fn alpn(protocols: &[String]) -> Result<Option<ALPN>> {
+    if protocols.is_empty() {
+        return Ok(None);
+    }
+    let mut h1 = false;
+    let mut h2 = false;
+    for protocol in protocols {
+        match protocol.as_str() {
+            "http/1.1" => h1 = true,
+            "h2" => h2 = true,
+            other => anyhow::bail!(
+                "Unsupported ALPN protocol: {} (use \"h2\" or \"http/1.1\")",
+                other
+            ),
+        }
+    }
+    Ok(Some(match (h1, h2) {
+        (true, true) => ALPN::H2H1,
+        (false, true) => ALPN::H2,
+        _ => ALPN::H1,
+    }))
+}
+
+/// Backend TLS settings for a route, with certificates and keys loaded once at startup.
+pub struct BackendTls {
+    ca: Option<Arc<CaType>>,
+    client_cert_key: Option<Arc<CertKey>>,
+    skip_verify: bool,
+    sni: Option<String>,
+}
+
+impl BackendTls {
+    pub fn load(options: &BackendTlsOptions) -> Result<Self> {
+        let ca = match &options.ca_cert {
+            Some(path) => {
+                let pem = std::fs::read(expand_home(path))
+                    .with_context(|| format!("Failed to read backend CA certificate {}", path))?;
+                let certs = X509::stack_from_pem(&pem)
+                    .with_context(|| format!("Failed to parse backend CA certificate {}", path))?;
+                if certs.is_empty() {
+                    anyhow::bail!("No certificates found in {}", path);
+                }
+                Some(Arc::new(certs.into_boxed_slice()))
+            }
+            None => None,
+        };
+
+        let client_cert_key = match (&options.client_cert, &options.client_key) {
+            (Some(cert_path), Some(key_path)) => {
+                let cert_pem = std::fs::read(expand_home(cert_path))
+                    .with_context(|| format!("Failed to read client certificate {}", cert_path))?;
+                let certs = X509::stack_from_pem(&cert_pem)
+                    .with_context(|| format!("Failed to parse client certificate {}", cert_path))?;
+                if certs.is_empty() {
+                    anyhow::bail!("No certificates found in {}", cert_path);
+                }
+                let key_pem = std::fs::read(expand_home(key_path))
+                    .with_context(|| format!("Failed to read client key {}", key_path))?;
+                let key = PKey::private_key_from_pem(&key_pem)
+                    .with_context(|| format!("Failed to parse client key {}", key_path))?;
+                Some(Arc::new(CertKey::new(certs, key)))
+            }
+            (None, None) => None,
+            _ => anyhow::bail!(
+                "backend_tls_options: client_cert and client_key must be set together"
+            ),
+        };
+
+        Ok(Self {
+            ca,
+            client_cert_key,
+            skip_verify: options.skip_verify,
+            sni: options.sni.clone(),
+        })
+    }
+
+    /// SNI for the backend connection, if overridden.
+    pub fn sni(&self) -> Option<&str> {
+        self.sni.as_deref()
+    }
+
+    /// Apply these settings to a TLS peer.
+    pub fn apply(&self, peer: &mut HttpPeer) {
+        if self.skip_verify {
+            peer.options.verify_cert = false;
+            peer.options.verify_hostname = false;
+        }
+        peer.options.ca = self.ca.clone();
+        peer.client_cert_key = self.client_cert_key.clone();
+    }
+}
+
+/// Background task re-issuing short-lived server certificates before they expire
+/// and swapping the new listener certificate in.
+pub struct CertRotation {
+    pub cert_manager: Arc<CertManager>,
+    /// Route hosts with per-host certificates
+    pub hosts: Vec<String>,
+    /// Names on the combined listener certificate
+    pub listener_names: Vec<String>,
+    pub listener_cert: Option<Arc<ListenerCert>>,
+    pub interval: Duration,
+}
+
+impl CertRotation {
+    fn rotate(&self) -> Result<()> {
+        for host in &self.hosts {
+            self.cert_manager.generate_server_cert(host)?;
+        }
+        if let Some(listener_cert) = &self.listener_cert {
+            self.cert_manager
+                .generate_combined_server_cert(&self.listener_names)?;
+            listener_cert.reload()?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BackgroundService for CertRotation {
+    async fn start(&self, mut shutdown: ShutdownWatch) {
+        loop {
+            tokio::select! {
+                _ = shutdown.changed() => return,
+                _ = tokio::time::sleep(self.interval) => {}
+            }
+            println!("🔄 Rotating short-lived server certificates...");
+            if let Err(e) = self.rotate() {
+                eprintln!("Failed to rotate server certificates: {:#}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use anyhow::{Context, Result};
+use openssl::ssl::{HandshakeError, SslConnector, SslMethod};
+use openssl::x509::X509;
+use std::io::Write;
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::process::Command;
+use std::time::Duration;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Outcome of a TLS handshake to one host through the local listener.
+pub struct HostCheck {
+    pub host: String,
+    pub port: u16,
+    /// Loopback address the connection went to
+    pub ip: IpAddr,
+    /// `None` when the handshake succeeded and the chain and host name verified.
+    pub error: Option<String>,
+}
+
+/// TLS client that validates against the system trust store, the way browsers and CLI tools
+/// on this machine would (rather than against the DevRelay CA file directly).
+pub fn system_trust_connector() -> Result<SslConnector> {
+    // Starts from OpenSSL's default verify paths; its compiled-in directory may not be the
+    // distribution's store though (e.g. a vendored build), so add whatever openssl-probe finds
+    let mut builder =
+        SslConnector::builder(SslMethod::tls_client()).context("Failed to create TLS client")?;
+    let probe = openssl_probe::probe();
+    if let Some(file) = &probe.cert_file
+        && let Ok(pem) = std::fs::read(file)
+    {
+        for cert in X509::stack_from_pem(&pem).unwrap_or_default() {
+            // Certificates already in the store are rejected as duplicates, which is fine
+            let _ = builder.cert_store_mut().add_cert(cert);
+        }
+    }
+    if let Some(dir) = &probe.cert_dir {
+        let _ = builder.load_verify_locations(None, Some(dir));
+    }
+    if std::env::consts::OS == "macos" {
+        for cert in macos_keychain_roots() {
+            let _ = builder.cert_store_mut().add_cert(cert);
+        }
+    }
+
+    Ok(builder.build())
+}
+
+/// Roots macOS trusts: the built-in ones, plus the certificates in the System keychain (where
+/// `Installer` adds roots) that the trust settings accept. Being in the keychain alone is not
+/// enough; a certificate can be there without trust, or with its trust removed.
+fn macos_keychain_roots() -> Vec<X509> {
+    let mut roots = keychain_certs("/System/Library/Keychains/SystemRootCertificates.keychain");
+    roots.extend(
+        keychain_certs("/Library/Keychains/System.keychain")
+            .into_iter()
+            .filter(macos_trusts),
+    );
+    roots
+}
+
+fn keychain_certs(keychain: &str) -> Vec<X509> {
+    let output = Command::new("security")
+        .args(["find-certificate", "-a", "-p", keychain])
+        .output();
+    match output {
+        Ok(output) if output.status.success() => {
+            X509::stack_from_pem(&output.stdout).unwrap_or_default()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Whether `security verify-cert` accepts the certificate on its own, i.e. the trust settings
+/// make it a trusted root. Checked offline, without fetching revocation information.
+fn macos_trusts(cert: &X509) -> bool {
+    let Ok(pem) = cert.to_pem() else {
+        return false;
+    };
+    let Ok(mut file) = tempfile::NamedTempFile::new() else {
+        return false;
+    };
+    if file.write_all(&pem).is_err() {
+        return false;
+    }
+    Command::new("security")
+        .args(["verify-cert", "-q", "-L", "-p", "basic", "-c"])
+        .arg(file.path())
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Handshake with the listener on `ip:port` (a loopback address), sending `host` as SNI and
+/// verifying the served chain and host name.
+pub fn check_host(connector: &SslConnector, host: &str, ip: IpAddr, port: u16) -> HostCheck {
+    let error = handshake(connector, host, SocketAddr::new(ip, port)).err();
+    HostCheck {
+        host: host.to_string(),
+        port,
+        ip,
+        error,
+    }
+}
+
+fn handshake(
+    connector: &SslConnector,
+    host: &str,
+    addr: SocketAddr,
+) -> std::result::Result<(), String> {
+    let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)
+        .map_err(|e| format!("cannot connect to {} ({}); is devrelay running?", addr, e))?;
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT)).ok();
+    stream.set_write_timeout(Some(CONNECT_TIMEOUT)).ok();
+
+    let config = connector.configure().map_err(|e| e.to_string())?;
+    match config.connect(host, stream) {
+        Ok(_) => Ok(()),
+        Err(HandshakeError::Failure(mid)) => {
+            let verify = mid.ssl().verify_result();
+            if verify.as_raw() != 0 {
+                Err(format!(
+                    "certificate verify failed: {} (X509 error {})",
+                    verify.error_string(),
+                    verify.as_raw()
+                ))
+            } else {
+                Err(format!("handshake failed: {}", mid.error()))
+            }
+        }
+        Err(e) => Err(format!("handshake failed: {}", e)),
+    }
+}
+
+/// Wait until something accepts connections on `ip:port`, for checks right after startup.
+pub fn wait_for_listener(ip: IpAddr, port: u16, timeout: Duration) -> bool {
+    let addr = SocketAddr::new(ip, port);
+    let deadline = std::time::Instant::now() + timeout;
+    while std::time::Instant::now() < deadline {
+        if TcpStream::connect_timeout(&addr, Duration::from_millis(200)).is_ok() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+    false
+}
+
+/// Print one line per host; returns whether every host verified.
+pub fn print_checks(checks: &[HostCheck]) -> bool {
+    for check in checks {
+        match &check.error {
+            None => println!("  ✅ {}:{} via {}", check.host, check.port, check.ip),
+            Some(error) => println!(
+                "  ❌ {}:{} via {}: {}",
+                check.host, check.port, check.ip, error
+            ),
+        }
+    }
+    checks.iter().all(|c| c.error.is_none())
+}